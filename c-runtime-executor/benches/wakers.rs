@@ -0,0 +1,171 @@
+/*
+Compares the Reactor's waker storage before and after the switch to `WakerSlab`:
+    - MutexMap: the old design, one `Mutex<HashMap<usize, Waker>>` locked by every `set_waker`, event and
+      deregistration, with ids counting up forever
+    - WakerSlab: per-slot AtomicWaker, ids reused through a free list
+Each round stores a Waker for an id and gets a copy back out, like `set_waker` followed by the event loop
+dispatching an event for it (not waking the copy keeps the executor out of the measurement).
+Run with `cargo bench --bench wakers`.
+ */
+use c_runtime_executor::{
+    runtime::{Runtime, Waker, WakerSlab},
+    Future, PollState,
+};
+use std::{
+    collections::HashMap,
+    hint::black_box,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const IDS_PER_THREAD: usize = 1_000;
+const ROUNDS: usize = 200;
+
+// the Reactor's waker storage as it was before WakerSlab
+#[derive(Default)]
+struct MutexMap {
+    wakers: Mutex<HashMap<usize, Waker>>,
+    next_id: AtomicUsize,
+}
+
+impl MutexMap {
+    fn next_id(&self) -> usize {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn set_waker(&self, waker: &Waker, id: usize) {
+        self.wakers.lock().unwrap().insert(id, waker.clone());
+    }
+
+    fn dispatch(&self, id: usize) -> Option<Waker> {
+        self.wakers.lock().unwrap().get(&id).cloned()
+    }
+
+    fn deregister(&self, id: usize) {
+        self.wakers.lock().unwrap().remove(&id);
+    }
+}
+
+// the operations every benchmark round uses, implemented by both designs
+trait Storage: Send + Sync + 'static {
+    fn allocate(&self) -> usize;
+    fn set_waker(&self, waker: &Waker, id: usize);
+    fn dispatch(&self, id: usize) -> Option<Waker>;
+    fn free(&self, id: usize);
+}
+
+impl Storage for MutexMap {
+    fn allocate(&self) -> usize {
+        self.next_id()
+    }
+    fn set_waker(&self, waker: &Waker, id: usize) {
+        MutexMap::set_waker(self, waker, id)
+    }
+    fn dispatch(&self, id: usize) -> Option<Waker> {
+        MutexMap::dispatch(self, id)
+    }
+    fn free(&self, id: usize) {
+        self.deregister(id)
+    }
+}
+
+impl Storage for WakerSlab {
+    fn allocate(&self) -> usize {
+        WakerSlab::allocate(self)
+    }
+    fn set_waker(&self, waker: &Waker, id: usize) {
+        self.register(id, waker)
+    }
+    fn dispatch(&self, id: usize) -> Option<Waker> {
+        self.waker(id)
+    }
+    fn free(&self, id: usize) {
+        WakerSlab::free(self, id)
+    }
+}
+
+// a real Waker to store; it belongs to a Runtime that has finished, so nothing is ever polled through it
+struct GetWaker;
+
+impl Future for GetWaker {
+    type Output = Waker;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        PollState::Ready(waker.clone())
+    }
+}
+
+// `threads` threads each own IDS_PER_THREAD ids and store and dispatch Wakers for them, ROUNDS times
+fn wake_rounds<S: Storage>(storage: Arc<S>, waker: &Waker, threads: usize) -> Duration {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let storage = storage.clone();
+            let waker = waker.clone();
+            thread::spawn(move || {
+                let ids: Vec<usize> = (0..IDS_PER_THREAD).map(|_| storage.allocate()).collect();
+                for _ in 0..ROUNDS {
+                    for &id in &ids {
+                        storage.set_waker(&waker, id);
+                        black_box(storage.dispatch(id));
+                    }
+                }
+                ids.into_iter().for_each(|id| storage.free(id));
+            })
+        })
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    start.elapsed()
+}
+
+// sources coming and going: allocate an id, store a Waker, free the id
+fn churn<S: Storage>(storage: Arc<S>, waker: &Waker, threads: usize) -> Duration {
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let storage = storage.clone();
+            let waker = waker.clone();
+            thread::spawn(move || {
+                for _ in 0..IDS_PER_THREAD * ROUNDS / 10 {
+                    let id = storage.allocate();
+                    storage.set_waker(&waker, id);
+                    storage.free(id);
+                }
+            })
+        })
+        .collect();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    start.elapsed()
+}
+
+fn report(name: &str, threads: usize, ops: usize, map: Duration, slab: Duration) {
+    let per_op = |d: Duration| d.as_nanos() as f64 / ops as f64;
+    println!(
+        "{name:<12} {threads:>2} threads: Mutex<HashMap> {:>7.1} ns/op   WakerSlab {:>7.1} ns/op   ({:.2}x)",
+        per_op(map),
+        per_op(slab),
+        map.as_secs_f64() / slab.as_secs_f64(),
+    );
+}
+
+fn main() {
+    let waker = Runtime::with_workers(1).block_on(GetWaker);
+
+    for threads in [1, 2, 4, 8] {
+        let ops = threads * IDS_PER_THREAD * ROUNDS;
+        let map = wake_rounds(Arc::new(MutexMap::default()), &waker, threads);
+        let slab = wake_rounds(Arc::new(WakerSlab::new()), &waker, threads);
+        report("set + wake", threads, ops, map, slab);
+    }
+
+    for threads in [1, 4] {
+        let ops = threads * IDS_PER_THREAD * ROUNDS / 10;
+        let map = churn(Arc::new(MutexMap::default()), &waker, threads);
+        let slab = churn(Arc::new(WakerSlab::new()), &waker, threads);
+        report("churn", threads, ops, map, slab);
+    }
+}
@@ -0,0 +1,220 @@
+/*
+delayserver:
+    - stand-in for the external delayserver the examples talk to, built on our own runtime
+    - `GET /<millis>/<message>` waits <millis> ms using a Reactor timer, then replies with <message>
+    - connections are kept alive unless the client sends `Connection: close`
+    - usage: cargo run --bin delayserver [-- <addr>]   (default 127.0.0.1:8080)
+ */
+use c_runtime_executor::{
+    future::Stream,
+    io::{AsyncRead, AsyncWrite},
+    net::{AsyncTcpStream, Incoming, TcpListener},
+    runtime::{self, Waker},
+    time::{sleep, Delay},
+    Future, PollState,
+};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:8080";
+
+// numbers the requests in the log, like the original delayserver
+static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    let mut rt = runtime::init();
+    let _context = rt.enter();      // the listener registers with `rt`'s Reactor
+
+    let listener = TcpListener::bind(&addr).unwrap_or_else(|e| panic!("failed to bind {addr}: {e}"));
+    println!("delayserver listening on {}", listener.local_addr().unwrap());
+    rt.block_on(Server { incoming: listener.incoming() });
+}
+
+// accepts connections forever and spawns a task serving each of them
+struct Server {
+    incoming: Incoming,
+}
+
+impl Future for Server {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            match self.incoming.poll_next(waker) {
+                PollState::Ready(Some(Ok((stream, _)))) => {
+                    runtime::spawn(Connection::new(stream));
+                }
+                PollState::Ready(Some(Err(e))) => eprintln!("accept failed: {e}"),
+                PollState::Ready(None) => break PollState::Ready(()),
+                PollState::NotReady => break PollState::NotReady,
+            }
+        }
+    }
+}
+
+enum State {
+    Reading,
+    Sleeping(Delay),
+    Writing { written: usize },
+    Closed,
+}
+
+/*
+Connection:
+    - one task per accepted stream
+    - Reading -> Sleeping -> Writing, then back to Reading for the next request on the same connection
+    - bytes of a pipelined request that arrived early stay in `buf`
+ */
+struct Connection {
+    stream: AsyncTcpStream,
+    buf: Vec<u8>,
+    response: Vec<u8>,
+    keep_alive: bool,
+    state: State,
+}
+
+impl Connection {
+    fn new(stream: AsyncTcpStream) -> Self {
+        Connection {
+            stream,
+            buf: vec![],
+            response: vec![],
+            keep_alive: true,
+            state: State::Reading,
+        }
+    }
+
+    // Some((delay, message, keep_alive)) once a full request is buffered; the request is drained from `buf`
+    fn parse_request(&mut self) -> Option<(Duration, Vec<u8>, bool)> {
+        let end = self.buf.windows(4).position(|w| w == b"\r\n\r\n")? + 4;
+        let head = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        let mut lines = head.split("\r\n");
+        let path = lines.next()?.split(' ').nth(1).unwrap_or("/");
+
+        let mut body_len = 0;
+        let mut keep_alive = true;
+        for line in lines {
+            let Some((name, value)) = line.split_once(':') else { continue };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => body_len = value.trim().parse().unwrap_or(0),
+                "connection" => keep_alive = !value.trim().eq_ignore_ascii_case("close"),
+                _ => (),
+            }
+        }
+        // a request body is ignored, but it must be consumed before the next request
+        if self.buf.len() < end + body_len {
+            return None;
+        }
+        self.buf.drain(..end + body_len);
+
+        // `/<millis>/<message>`; a path that doesn't start with a number replies immediately
+        let mut parts = path.trim_start_matches('/').splitn(2, '/');
+        let millis = parts.next().and_then(|m| m.parse().ok());
+        let message = parts.next().unwrap_or_default();
+        let delay = Duration::from_millis(millis.unwrap_or(0));
+
+        let n = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+        println!("#{n} - {}ms: {message}", delay.as_millis());
+        Some((delay, message.as_bytes().to_vec(), keep_alive))
+    }
+}
+
+impl Future for Connection {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            match self.state {
+                State::Reading => {
+                    if let Some((delay, message, keep_alive)) = self.parse_request() {
+                        self.response = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: {}\r\n\r\n",
+                            message.len(),
+                            if keep_alive { "keep-alive" } else { "close" },
+                        )
+                        .into_bytes();
+                        self.response.extend_from_slice(&message);
+                        self.keep_alive = keep_alive;
+                        self.state = State::Sleeping(sleep(delay));
+                        continue;
+                    }
+                    let mut chunk = [0u8; 4096];
+                    match self.stream.poll_read(waker, &mut chunk) {
+                        // the peer may only close between requests; a half-read request is dropped
+                        PollState::Ready(Ok(0)) => self.state = State::Closed,
+                        PollState::Ready(Ok(n)) => self.buf.extend_from_slice(&chunk[..n]),
+                        PollState::Ready(Err(e)) => {
+                            eprintln!("read failed: {e}");
+                            self.state = State::Closed;
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State::Sleeping(ref mut delay) => match delay.poll(waker) {
+                    PollState::Ready(()) => self.state = State::Writing { written: 0 },
+                    PollState::NotReady => break PollState::NotReady,
+                },
+
+                State::Writing { ref mut written } => {
+                    match self.stream.poll_write(waker, &self.response[*written..]) {
+                        PollState::Ready(Ok(n)) => {
+                            *written += n;
+                            if *written == self.response.len() {
+                                self.state = if self.keep_alive { State::Reading } else { State::Closed };
+                            }
+                        }
+                        PollState::Ready(Err(e)) => {
+                            eprintln!("write failed: {e}");
+                            self.state = State::Closed;
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State::Closed => break PollState::Ready(()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use c_runtime_executor::{http::Http, runtime::Runtime};
+    use std::{sync::mpsc, thread, time::Instant};
+
+    // serves on a loopback port from a Runtime of its own; the thread runs until the test process exits
+    fn start() -> String {
+        let (addr_tx, addr_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut rt = Runtime::with_workers(1);
+            let _context = rt.enter();
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            addr_tx.send(listener.local_addr().unwrap()).unwrap();
+            rt.block_on(Server { incoming: listener.incoming() });
+        });
+        format!("http://{}", addr_rx.recv().unwrap())
+    }
+
+    #[test]
+    fn replies_with_the_message_after_the_delay() {
+        let url = start();
+        let mut rt = Runtime::with_workers(1);
+
+        let started = Instant::now();
+        let response = rt.block_on(Http::get(&format!("{url}/300/HelloAsyncAwait"))).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text(), "HelloAsyncAwait");
+
+        // the connection is kept alive; a path without a delay is answered right away
+        let started = Instant::now();
+        let response = rt.block_on(Http::get(&format!("{url}/now"))).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(300));
+        assert_eq!(response.text(), "");
+    }
+}
@@ -1,4 +1,6 @@
 use crate::runtime::Waker;  // pull in the Waker to use it
+use crate::time::{sleep, Delay};
+use std::time::Duration;
 
 pub trait Future {
     type Output;
@@ -10,6 +12,230 @@ pub enum PollState<T> {
     NotReady,
 }
 
+/*
+Stream:
+    - like a Future, but resolves to a sequence of values instead of a single one
+    - Ready(Some(item)): the next item; Ready(None): the stream is finished and must not be polled again
+    - NotReady: no item available yet; the Waker is woken once polling again can make progress
+    - combinators are lazy: nothing happens until the resulting stream or future is polled
+
+Coroutines can't hold a `&mut` to a stream across a `.wait`, so they iterate with `into_future`,
+which moves the stream into the future and hands it back together with the next item:
+    State::Wait1(Box<StreamFuture<S>>) => match f1.poll(waker) {
+        PollState::Ready((Some(item), stream)) => { /* use item */ self.state = State::Wait1(Box::new(stream.into_future())) }
+        PollState::Ready((None, _)) => { /* stream finished */ }
+        PollState::NotReady => break PollState::NotReady,
+    }
+ */
+pub trait Stream {
+    type Item;
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>>;
+
+    // future resolving to the next item; None once the stream is finished
+    fn next(&mut self) -> Next<'_, Self>
+    where
+        Self: Sized,
+    {
+        Next { stream: self }
+    }
+
+    // owning version of `next`: resolves to the next item and the stream itself
+    fn into_future(self) -> StreamFuture<Self>
+    where
+        Self: Sized,
+    {
+        StreamFuture { stream: Some(self) }
+    }
+
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item) -> T,
+    {
+        Map { stream: self, f }
+    }
+
+    // yields only the items `predicate` returns true for
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Filter { stream: self, predicate }
+    }
+
+    // yields at most `n` items; the inner stream isn't polled after that
+    fn take(self, n: usize) -> Take<Self>
+    where
+        Self: Sized,
+    {
+        Take { stream: self, left: n }
+    }
+
+    // future calling `f` with every item, resolving once the stream is finished
+    fn for_each<F>(self, f: F) -> ForEach<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Item),
+    {
+        ForEach { stream: self, f }
+    }
+
+    // future resolving to all items, e.g. `stream.collect::<Vec<_>>()`
+    fn collect<C>(self) -> Collect<Self, C>
+    where
+        Self: Sized,
+        C: Default + Extend<Self::Item>,
+    {
+        Collect { stream: self, items: Some(C::default()) }
+    }
+}
+
+pub struct Next<'a, S: Stream> {
+    stream: &'a mut S,
+}
+
+impl<S: Stream> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.stream.poll_next(waker)
+    }
+}
+
+pub struct StreamFuture<S: Stream> {
+    stream: Option<S>,      // handed back with the item
+}
+
+impl<S: Stream> Future for StreamFuture<S> {
+    type Output = (Option<S::Item>, S);
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let stream = self.stream.as_mut().expect("Polled a resolved future");
+        match stream.poll_next(waker) {
+            PollState::Ready(item) => PollState::Ready((item, self.stream.take().unwrap())),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+pub struct Map<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S: Stream, T, F: FnMut(S::Item) -> T> Stream for Map<S, F> {
+    type Item = T;
+
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>> {
+        match self.stream.poll_next(waker) {
+            PollState::Ready(item) => PollState::Ready(item.map(&mut self.f)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+pub struct Filter<S, F> {
+    stream: S,
+    predicate: F,
+}
+
+impl<S: Stream, F: FnMut(&S::Item) -> bool> Stream for Filter<S, F> {
+    type Item = S::Item;
+
+    // skipped items don't return NotReady; we keep polling until an item passes or the stream has to wait
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>> {
+        loop {
+            match self.stream.poll_next(waker) {
+                PollState::Ready(Some(item)) if !(self.predicate)(&item) => continue,
+                other => break other,
+            }
+        }
+    }
+}
+
+pub struct Take<S> {
+    stream: S,
+    left: usize,
+}
+
+impl<S: Stream> Stream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>> {
+        if self.left == 0 {
+            return PollState::Ready(None);
+        }
+        match self.stream.poll_next(waker) {
+            PollState::Ready(Some(item)) => {
+                self.left -= 1;
+                PollState::Ready(Some(item))
+            }
+            PollState::Ready(None) => {
+                self.left = 0;
+                PollState::Ready(None)
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+pub struct ForEach<S, F> {
+    stream: S,
+    f: F,
+}
+
+impl<S: Stream, F: FnMut(S::Item)> Future for ForEach<S, F> {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        loop {
+            match self.stream.poll_next(waker) {
+                PollState::Ready(Some(item)) => (self.f)(item),
+                PollState::Ready(None) => break PollState::Ready(()),
+                PollState::NotReady => break PollState::NotReady,
+            }
+        }
+    }
+}
+
+pub struct Collect<S, C> {
+    stream: S,
+    items: Option<C>,       // taken when the stream is finished
+}
+
+impl<S: Stream, C: Default + Extend<S::Item>> Future for Collect<S, C> {
+    type Output = C;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let items = self.items.as_mut().expect("Polled a resolved future");
+        loop {
+            match self.stream.poll_next(waker) {
+                PollState::Ready(Some(item)) => items.extend(Some(item)),
+                PollState::Ready(None) => break PollState::Ready(self.items.take().unwrap()),
+                PollState::NotReady => break PollState::NotReady,
+            }
+        }
+    }
+}
+
+// stream yielding the items of an iterator; always ready
+pub struct Iter<I> {
+    iter: I,
+}
+
+pub fn iter<I: IntoIterator>(items: I) -> Iter<I::IntoIter> {
+    Iter { iter: items.into_iter() }
+}
+
+impl<I: Iterator> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(&mut self, _waker: &Waker) -> PollState<Option<Self::Item>> {
+        PollState::Ready(self.iter.next())
+    }
+}
+
 
 pub struct JoinAll<F: Future> {
     futures: Vec<(bool, F)>,
@@ -57,3 +283,54 @@ pub fn join_all<F: Future>(futures: Vec<F>) -> JoinAll<F> {
         finished_count: 0,
     }
 }
+
+// Error returned by `Timeout` when the deadline passed before the inner future resolved
+#[derive(Debug)]
+pub struct Elapsed;
+
+impl std::fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "deadline has elapsed")
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+pub struct Timeout<F: Future> {
+    future: Option<F>,      // None once the deadline passed and the inner future was dropped
+    delay: Delay,
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    // The inner future and the Delay are polled with the same Waker; whichever becomes ready first wins
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if let Some(future) = self.future.as_mut() {
+            if let PollState::Ready(value) = future.poll(waker) {
+                return PollState::Ready(Ok(value));
+            }
+        }
+        match self.delay.poll(waker) {
+            PollState::Ready(()) => {
+                self.future = None;     // drop the inner future so it releases its Reactor registration
+                PollState::Ready(Err(Elapsed))
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/*
+`timeout`
+- arg: the maximum duration to wait and the future to run
+- return: `Timeout<F>` future
+Races the future against a Reactor timer. Resolves to Ok(output) if the future finishes first,
+otherwise drops the future and resolves to Err(Elapsed).
+*/
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future: Some(future),
+        delay: sleep(duration),
+    }
+}
@@ -1,95 +1,480 @@
-use crate::{future::PollState, runtime::{self, reactor, Waker}, Future};
-use mio::Interest;
-use std::io::{ErrorKind, Read, Write};
-
-fn get_req(path: &str) -> String {
-    format!(
-        "GET {path} HTTP/1.1\r\n\
-             Host: localhost\r\n\
-             Connection: close\r\n\
-             \r\n"
-    )
+use crate::{
+    future::{PollState, Stream},
+    io::{AsyncRead, AsyncWrite},
+    net::AsyncTcpStream,
+    runtime::Waker,
+    time::{sleep, Delay},
+    Future,
+};
+#[cfg(unix)]
+use crate::net::AsyncUnixStream;
+use std::{
+    fmt,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+pub use request::{Method, Request};
+pub use response::Response;
+pub use url::Url;
+
+mod pool;
+mod request;
+mod response;
+mod transport;
+mod url;
+
+pub(crate) use pool::Pool;
+use pool::{pool, Checkout, Connection};
+use response::ResponseParser;
+use transport::Transport;
+
+// how long to wait before connecting again to a Unix socket whose listen backlog is full
+const CONNECT_BACKOFF: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub enum HttpError {
+    Connect(io::Error),         // could not establish the TCP connection
+    Write(io::Error),           // sending the request failed
+    Read(io::Error),            // reading the response failed
+    MalformedResponse(String),  // the server sent something that is not an HTTP response
+    InvalidUrl(String),         // the URL could not be parsed
+    UnsupportedScheme(String),  // only `http://` URLs are supported
+    InvalidRequest(String),     // a method or header that can't be sent as it is
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Connect(e) => write!(f, "connect failed: {e}"),
+            HttpError::Write(e) => write!(f, "writing request failed: {e}"),
+            HttpError::Read(e) => write!(f, "reading response failed: {e}"),
+            HttpError::MalformedResponse(reason) => write!(f, "malformed response: {reason}"),
+            HttpError::InvalidUrl(reason) => write!(f, "invalid url: {reason}"),
+            HttpError::UnsupportedScheme(scheme) => write!(f, "unsupported scheme {scheme:?}"),
+            HttpError::InvalidRequest(reason) => write!(f, "invalid request: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HttpError::Connect(e) | HttpError::Write(e) | HttpError::Read(e) => Some(e),
+            HttpError::MalformedResponse(_)
+            | HttpError::InvalidUrl(_)
+            | HttpError::UnsupportedScheme(_)
+            | HttpError::InvalidRequest(_) => None,
+        }
+    }
 }
 
 pub struct Http;
 
 impl Http {
-    pub fn get(path: &str) -> impl Future<Output = String> {
-        HttpGetFuture::new(path)
+    // `url` is a full URL such as `http://127.0.0.1:8080/600/HelloAsyncAwait`
+    pub fn get(url: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(Request::get(url))
+    }
+
+    pub fn post(url: &str, body: impl Into<Vec<u8>>) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(Request::post(url).body(body))
+    }
+
+    pub fn put(url: &str, body: impl Into<Vec<u8>>) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(Request::put(url).body(body))
+    }
+
+    pub fn delete(url: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(Request::delete(url))
+    }
+
+    // sends a request built with `Request`, e.g. `Request::post(url).header("Content-Type", "text/plain").body("hi")`
+    pub fn send(request: Request) -> impl Future<Output = Result<Response, HttpError>> {
+        HttpFuture { exchange: Exchange::new(request) }
+    }
+
+    // like `get`, but the body is yielded in chunks as it arrives instead of being buffered
+    pub fn get_stream(url: &str) -> BodyStream {
+        Http::send_stream(Request::get(url))
+    }
+
+    pub fn send_stream(request: Request) -> BodyStream {
+        BodyStream { exchange: Exchange::new(request), head: None, done: false }
+    }
+
+    // client sending every request to the same server, e.g. `Http::client("http://127.0.0.1:8080")`
+    pub fn client(base_url: &str) -> Client {
+        Client { base_url: base_url.to_string(), unix_socket: None }
     }
 }
 
-struct HttpGetFuture {
-    stream: Option<mio::net::TcpStream>,
-    buffer: Vec<u8>,
-    path: String,
-    id: usize,
+pub struct Client {
+    base_url: String,
+    unix_socket: Option<PathBuf>,
 }
 
-impl HttpGetFuture {
-    fn new(path: &str) -> Self {
-        let id = reactor().next_id();       // retrive new ID from the reactor when future is created
+impl Client {
+    // sends every request over the Unix socket at `path`,
+    // e.g. `Http::client("http://localhost").unix_socket("/run/sidecar.sock")`
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: impl AsRef<Path>) -> Self {
+        self.unix_socket = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    // request to `path` resolved against the base URL; an invalid base URL is reported when it's sent
+    pub fn request(&self, method: Method, path: &str) -> Request {
+        let request = Request::with_url(method, Url::parse(&self.base_url).and_then(|base| base.join(path)));
+        match &self.unix_socket {
+            #[cfg(unix)]
+            Some(socket) => request.unix_socket(socket),
+            _ => request,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(self.request(Method::Get, path))
+    }
+
+    pub fn get_stream(&self, path: &str) -> BodyStream {
+        Http::send_stream(self.request(Method::Get, path))
+    }
+
+    pub fn post(&self, path: &str, body: impl Into<Vec<u8>>) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(self.request(Method::Post, path).body(body))
+    }
+
+    pub fn put(&self, path: &str, body: impl Into<Vec<u8>>) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(self.request(Method::Put, path).body(body))
+    }
+
+    pub fn delete(&self, path: &str) -> impl Future<Output = Result<Response, HttpError>> {
+        Http::send(self.request(Method::Delete, path))
+    }
+}
+
+/*
+An Exchange sends one request and reads its response; it goes through these states, each of them driven by readiness events from the Reactor:
+    - Start: nothing happened yet; the first poll picks up the Runtime's pool and reports an invalid URL
+    - Checkout: asks the pool for an idle keep-alive connection to the host; if there is none, a new
+      connection is opened, unless the per-host limit is reached and we wait for a connection to be returned
+    - Backoff: a Unix socket's listen backlog was full, so nothing could connect; back to Checkout after a while
+    - Connecting: waiting for the connect to finish (or fail), which the socket signals with a WRITABLE event
+    - Writing: writing the request; partial writes continue on the next WRITABLE event
+    - Reading: parsing the response as READABLE events come in, until the body is complete
+               (or, without Content-Length and chunked encoding, until the server closes the connection)
+    - Done: finished; the connection went back to the pool or was closed
+The connection is registered once for READABLE and WRITABLE, so no state changes its interest; each state
+only acts on the readiness it needs, and the Waker stored by the current state decides which task is woken.
+`HttpFuture` drives it until the whole response is there, `BodyStream` hands out the body as it arrives.
+ */
+enum State {
+    Start,
+    Checkout,
+    Backoff(Delay),
+    Connecting,
+    Writing { written: usize },
+    Reading,
+    Done,
+}
+
+struct Exchange {
+    conn: Option<Connection>,
+    response: Option<ResponseParser>,       // the response is parsed as data arrives; taken once finished
+    url: Result<Url, Option<HttpError>>,    // a URL or request error is taken and reported on the first poll
+    method: Method,
+    request: Vec<u8>,                       // serialized request
+    state: State,
+    reused: bool,                           // `conn` came from the pool and may have been closed by the server
+    unix_socket: Option<PathBuf>,           // connect here instead of the URL's host
+    pool: Option<Arc<Pool>>,                // the polling Runtime's pool, kept so `drop` doesn't need a context
+}
+
+impl Exchange {
+    fn new(request: Request) -> Self {
+        let unix_socket = request.socket_path().map(Path::to_path_buf);
+        let (url, method, request) = request.encode();
         Self {
-            stream: None,
-            buffer: vec![],
-            path: path.to_string(),
-            id,
+            conn: None,
+            response: Some(ResponseParser::new(method != Method::Head)),   // a response to HEAD has no body
+            url: url.map_err(Some),
+            method,
+            request,
+            state: State::Start,
+            reused: false,
+            unix_socket,
+            pool: None,
         }
     }
 
-    fn write_request(&mut self) {
-        let stream = std::net::TcpStream::connect("127.0.0.1:8080").unwrap();
-        stream.set_nonblocking(true).unwrap();
-        let mut stream = mio::net::TcpStream::from_std(stream);
-        stream.write_all(get_req(&self.path).as_bytes()).unwrap();
-        self.stream = Some(stream);
+    fn pool(&self) -> &Pool {
+        self.pool.as_ref().expect("pool is set on the first poll")
     }
+
+    fn url(&self) -> &Url {
+        self.url.as_ref().expect("url is checked on the first poll")
+    }
+
+    // pool key; connections are shared between all requests to the same host and port (or Unix socket)
+    fn key(&self) -> String {
+        match &self.unix_socket {
+            Some(path) => format!("unix:{}", path.display()),
+            None => format!("{}:{}", self.url().host(), self.url().port()),
+        }
+    }
+
+    // resolves the host (a blocking lookup, see `Url::socket_addr`) and starts a non-blocking connect;
+    // the stream registers itself with the Reactor
+    fn connect(&mut self) -> Result<(), HttpError> {
+        let stream = match &self.unix_socket {
+            #[cfg(unix)]
+            Some(path) => Transport::Unix(AsyncUnixStream::connect(path).map_err(HttpError::Connect)?),
+            _ => {
+                let addr = self.url().socket_addr().map_err(HttpError::Connect)?;
+                Transport::Tcp(AsyncTcpStream::connect(addr).map_err(HttpError::Connect)?)
+            }
+        };
+        self.conn = Some(Connection::new(&self.key(), stream));
+        Ok(())
+    }
+
+    // Ready(Ok(())) once the whole request is written
+    fn write_request(&mut self, waker: &Waker) -> PollState<Result<(), HttpError>> {
+        let State::Writing { written } = &mut self.state else {
+            unreachable!("write_request called outside of the Writing state");
+        };
+        let stream = &mut self.conn.as_mut().unwrap().stream;
+        while *written < self.request.len() {
+            match stream.poll_write(waker, &self.request[*written..]) {
+                PollState::Ready(Ok(0)) => return PollState::Ready(Err(HttpError::Write(ErrorKind::WriteZero.into()))),
+                PollState::Ready(Ok(n)) => *written += n,
+                PollState::Ready(Err(e)) => return PollState::Ready(Err(HttpError::Write(e))),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(Ok(()))
+    }
+
+    // deregister stream from the `Poll` and free its slot in the pool; called on every error path
+    fn close(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool().discard(conn);
+        }
+    }
+
+    // A pooled connection the server closed while it was idle fails on the first write or read. That says
+    // nothing about the request, so it is sent again on another connection. Requests that aren't idempotent
+    // are only retried while writing; once fully sent, the server may have acted on them.
+    fn retry(&mut self) -> bool {
+        let safe = self.method.is_idempotent() || matches!(self.state, State::Writing { .. });
+        let retry = safe && self.reused && self.response.as_ref().is_some_and(|r| r.is_empty());
+        if retry {
+            self.close();
+            self.reused = false;
+            self.state = State::Checkout;
+        }
+        retry
+    }
+
+    // hands the connection back to the pool (or closes it) once `poll_progress` reported completion
+    fn finish(&mut self) -> Result<Response, HttpError> {
+        let response = self.response.take().expect("Polled a resolved future");
+        if let Some(conn) = self.conn.take() {
+            if response.is_reusable() {
+                self.pool().checkin(conn);  // keep the connection open for the next request to this host
+            } else {
+                self.pool().discard(conn);
+            }
+        }
+        self.state = State::Done;
+        response.finish()
+    }
+
+    // status line and headers, once they have arrived
+    fn head(&self) -> Option<Response> {
+        self.response.as_ref().and_then(|r| r.head())
+    }
+
+    // body bytes decoded so far and not taken yet
+    fn take_body(&mut self) -> Vec<u8> {
+        self.response.as_mut().map(|r| r.take_body()).unwrap_or_default()
+    }
+
+    fn fail(&mut self, error: HttpError) -> PollState<Result<bool, HttpError>> {
+        self.close();
+        self.state = State::Done;
+        PollState::Ready(Err(error))
+    }
+
+    /*
+    poll_progress:
+        - drives the exchange until more of the response has arrived
+        - Ready(Ok(false)): new data was parsed, the response isn't complete yet
+        - Ready(Ok(true)): the response is complete (or the server closed the connection); call `finish`
+     */
+    fn poll_progress(&mut self, waker: &Waker) -> PollState<Result<bool, HttpError>> {
+        loop {
+            match self.state {
+                State::Start => {
+                    println!("FIRST POLL - START OPERATION");
+                    if let Err(e) = &mut self.url {
+                        let e = e.take().expect("Polled a resolved future");
+                        return self.fail(e);
+                    }
+                    self.pool = Some(pool());
+                    self.state = State::Checkout;
+                }
+
+                State::Checkout => match self.pool().checkout(&self.key(), waker) {
+                    Ok(Checkout::Idle(conn)) => {
+                        self.conn = Some(conn);
+                        self.reused = true;
+                        self.state = State::Writing { written: 0 };
+                    }
+                    Ok(Checkout::Connect) => match self.connect() {
+                        Ok(()) => self.state = State::Connecting,
+                        Err(HttpError::Connect(e)) if e.kind() == ErrorKind::WouldBlock => {
+                            self.pool().release(&self.key());    // another request may get through meanwhile
+                            self.state = State::Backoff(sleep(CONNECT_BACKOFF));
+                        }
+                        Err(e) => {
+                            self.pool().release(&self.key());    // the reserved slot was never used
+                            return self.fail(e);
+                        }
+                    },
+                    Ok(Checkout::Wait) => break PollState::NotReady,
+                    Err(e) => return self.fail(HttpError::Connect(e)),     // the Runtime is shutting down
+                },
+
+                State::Backoff(ref mut delay) => match delay.poll(waker) {
+                    PollState::Ready(()) => self.state = State::Checkout,
+                    PollState::NotReady => break PollState::NotReady,
+                },
+
+                State::Connecting => match self.conn.as_mut().unwrap().stream.poll_connected(waker) {
+                    PollState::Ready(Ok(())) => self.state = State::Writing { written: 0 },
+                    PollState::Ready(Err(e)) => return self.fail(HttpError::Connect(e)),
+                    PollState::NotReady => break PollState::NotReady,
+                },
+
+                State::Writing { .. } => match self.write_request(waker) {
+                    // the request is sent, from now on we're only interested in the response
+                    PollState::Ready(Ok(())) => self.state = State::Reading,
+                    PollState::Ready(Err(_)) if self.retry() => {}
+                    PollState::Ready(Err(e)) => return self.fail(e),
+                    PollState::NotReady => break PollState::NotReady,
+                },
+
+                State::Reading => {
+                    let mut buff = vec![0u8; 4096];
+                    match self.conn.as_mut().unwrap().stream.poll_read(waker, &mut buff) {
+                        PollState::Ready(Ok(0)) if self.retry() => {}
+                        // the server closed the connection, so it's deregistered and not returned to the pool
+                        PollState::Ready(Ok(0)) => break PollState::Ready(Ok(true)),
+                        PollState::Ready(Ok(n)) => {
+                            let response = self.response.as_mut().unwrap();
+                            if let Err(e) = response.feed(&buff[0..n]) {
+                                return self.fail(e);
+                            }
+                            // Content-Length or chunked body fully received; no need to wait for the server to close
+                            break PollState::Ready(Ok(response.is_complete()));
+                        }
+                        PollState::Ready(Err(_)) if self.retry() => {}
+                        PollState::Ready(Err(e)) => return self.fail(HttpError::Read(e)),
+                        PollState::NotReady => break PollState::NotReady,
+                    }
+                }
+
+                State::Done => panic!("Polled a resolved future"),
+            }
+        }
+    }
+}
+
+struct HttpFuture {
+    exchange: Exchange,
 }
 
-impl Future for HttpGetFuture {
-    type Output = String;
+impl Future for HttpFuture {
+    type Output = Result<Response, HttpError>;
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
-        if self.stream.is_none() {
-            println!("FIRST POLL - START OPERATION");
-            self.write_request();
-            // remove the following line to poll TcpStream immediately
-            // return PollState::NotReady;
-            
-            // no longer register directly with Registry:
-            // runtime::registry()
-            //     .register(self.stream.as_mut().unwrap(), Token(0), Interest::READABLE)
-            //         .unwrap();
-            //
-
-            // register interest with Poll instance and register the Waker reaceived with the Reactor
-            let stream = self.stream.as_mut().unwrap();
-            runtime::reactor().register(stream, Interest::READABLE, self.id);
-            runtime::reactor().set_waker(waker, self.id);
+        loop {
+            match self.exchange.poll_progress(waker) {
+                PollState::Ready(Ok(true)) => break PollState::Ready(self.exchange.finish()),
+                PollState::Ready(Ok(false)) => continue,
+                PollState::Ready(Err(e)) => break PollState::Ready(Err(e)),
+                PollState::NotReady => break PollState::NotReady,
+            }
         }
+    }
+}
+
+/*
+BodyStream:
+    - yields the response body in chunks as the Reactor reports the socket readable,
+      instead of buffering the whole body like `Http::get`
+    - chunked transfer-encoding is already decoded; a failure is yielded as the last item
+    - `head` gives the status and headers once they have arrived
+ */
+pub struct BodyStream {
+    exchange: Exchange,
+    head: Option<Response>,
+    done: bool,
+}
+
+impl BodyStream {
+    // status and headers; the body of the returned Response is always empty
+    pub fn head(&self) -> Option<&Response> {
+        self.head.as_ref()
+    }
+}
 
-        let mut buff = vec![0u8; 4096];
+impl Stream for BodyStream {
+    type Item = Result<Vec<u8>, HttpError>;
+
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>> {
+        if self.done {
+            return PollState::Ready(None);
+        }
         loop {
-            match self.stream.as_mut().unwrap().read(&mut buff) {
-                // deregister stream from the `Poll` when done
-                Ok(0) => {
-                    let s = String::from_utf8_lossy(&self.buffer);
-                    runtime::reactor().deregister(self.stream.as_mut().unwrap(), self.id);
-                    break PollState::Ready(s.to_string());
-                }
-                Ok(n) => {
-                    self.buffer.extend(&buff[0..n]);
-                    continue;
+            match self.exchange.poll_progress(waker) {
+                PollState::Ready(Ok(false)) => {
+                    if self.head.is_none() {
+                        self.head = self.exchange.head();
+                    }
+                    let chunk = self.exchange.take_body();
+                    if !chunk.is_empty() {
+                        break PollState::Ready(Some(Ok(chunk)));
+                    }
                 }
-                Err(e) if e.kind() == ErrorKind::WouldBlock => {
-                    runtime::reactor().set_waker(waker, self.id);
-                    break PollState::NotReady;
+                PollState::Ready(Ok(true)) => {
+                    if self.head.is_none() {
+                        self.head = self.exchange.head();
+                    }
+                    self.done = true;
+                    break match self.exchange.finish() {
+                        Ok(rest) if rest.body().is_empty() => PollState::Ready(None),
+                        Ok(rest) => PollState::Ready(Some(Ok(rest.into_body()))),
+                        Err(e) => PollState::Ready(Some(Err(e))),
+                    };
                 }
-                Err(e) if e.kind() == ErrorKind::Interrupted => {
-                    continue;
+                PollState::Ready(Err(e)) => {
+                    self.done = true;
+                    break PollState::Ready(Some(Err(e)));
                 }
-                Err(e) => panic!("{e:?}"),
+                PollState::NotReady => break PollState::NotReady,
             }
         }
     }
 }
+
+// A future dropped before it resolved (e.g. its task was aborted) must not leave its socket and Waker behind
+impl Drop for Exchange {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,216 @@
+use super::transport::Transport;
+use crate::runtime::{self, shutdown_error, Waker};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// idle connections older than this are closed instead of reused
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+// open connections (idle and in use) allowed per host:port; further requests wait for one to be returned
+pub const MAX_CONNECTIONS_PER_HOST: usize = 100;
+
+// every Runtime has its own pool, since its connections are registered with that Runtime's Reactor
+pub fn pool() -> Arc<Pool> {
+    runtime::context().pool
+}
+
+/*
+Connection:
+    - a TCP or Unix stream registered with the Reactor
+    - stays registered while it sits idle in the pool, so reusing it doesn't cost a new token
+ */
+pub struct Connection {
+    pub stream: Transport,
+    key: String,            // host:port (or Unix socket path) the connection belongs to
+}
+
+impl Connection {
+    pub fn new(key: &str, stream: Transport) -> Self {
+        Self { stream, key: key.to_string() }
+    }
+}
+
+struct Idle {
+    conn: Connection,
+    since: Instant,
+}
+
+#[derive(Default)]
+struct Host {
+    idle: Vec<Idle>,
+    open: usize,            // idle and in use
+    waiters: Vec<Waker>,    // requests waiting for a free slot, one Waker per task
+}
+
+pub enum Checkout {
+    Idle(Connection),       // reuse this connection
+    Connect,                // a slot was reserved; open a new connection
+    Wait,                   // limit reached; woken once a connection is returned or closed, or on shutdown
+}
+
+/*
+Pool:
+    - HTTP/1.1 keep-alive connections keyed by host:port, or by socket path for Unix sockets
+    - `checkout` hands out an idle connection, reserves a slot for a new one, or queues the caller
+    - `checkin` returns a connection whose response was read completely; `discard` closes one
+    - both `checkout` and `checkin` close the expired idle connections of every host, not just their own,
+      so connections to a host that's never contacted again don't stay open forever
+    - `shutdown` wakes every waiting request; from then on `checkout` fails with the Reactor's shutdown error
+ */
+pub struct Pool {
+    hosts: Mutex<HashMap<String, Host>>,
+    max_per_host: usize,
+    idle_timeout: Duration,
+    shutdown: AtomicBool,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new(MAX_CONNECTIONS_PER_HOST, IDLE_TIMEOUT)
+    }
+}
+
+impl Pool {
+    pub fn new(max_per_host: usize, idle_timeout: Duration) -> Self {
+        Self { hosts: Mutex::new(HashMap::new()), max_per_host, idle_timeout, shutdown: AtomicBool::new(false) }
+    }
+
+    pub fn checkout(&self, key: &str, waker: &Waker) -> io::Result<Checkout> {
+        let mut hosts = self.hosts.lock().unwrap();
+        // checked under the lock, so a waiter can't be queued after `shutdown` drained them
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(shutdown_error());
+        }
+        self.evict_expired(&mut hosts);
+        let host = hosts.entry(key.to_string()).or_default();
+
+        // most recently used first; connections the server closed are dropped on the way
+        while let Some(Idle { mut conn, .. }) = host.idle.pop() {
+            if conn.stream.is_alive() {
+                return Ok(Checkout::Idle(conn));
+            }
+            host.open -= 1;
+            drop(conn);     // dropping the stream deregisters it from the Reactor
+        }
+
+        if host.open < self.max_per_host {
+            host.open += 1;
+            Ok(Checkout::Connect)
+        } else {
+            // a task polled again while it waits replaces its Waker instead of queueing another one
+            match host.waiters.iter_mut().find(|w| w.will_wake(waker)) {
+                Some(waiter) => *waiter = waker.clone(),
+                None => host.waiters.push(waker.clone()),
+            }
+            Ok(Checkout::Wait)
+        }
+    }
+
+    pub fn checkin(&self, conn: Connection) {
+        conn.stream.clear_waker();          // events on an idle connection must not wake the last user
+        let mut hosts = self.hosts.lock().unwrap();
+        self.evict_expired(&mut hosts);
+        let host = hosts.entry(conn.key.clone()).or_default();
+        host.idle.push(Idle { conn, since: Instant::now() });
+        wake_all(host);
+    }
+
+    // closes a connection that can't be reused and frees its slot
+    pub fn discard(&self, conn: Connection) {
+        let key = conn.key.clone();
+        drop(conn);
+        self.release(&key);
+    }
+
+    // frees a slot reserved by `Checkout::Connect`, e.g. when connecting failed
+    pub fn release(&self, key: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(host) = hosts.get_mut(key) {
+            host.open -= 1;
+            wake_all(host);
+        }
+    }
+
+    // called when the Runtime shuts down; the woken requests see the error on their next `checkout`
+    pub fn shutdown(&self) {
+        let mut hosts = self.hosts.lock().unwrap();
+        self.shutdown.store(true, Ordering::Release);
+        hosts.values_mut().for_each(wake_all);
+    }
+
+    // closes idle connections older than `idle_timeout` and forgets hosts with nothing left
+    fn evict_expired(&self, hosts: &mut HashMap<String, Host>) {
+        hosts.retain(|_, host| {
+            let before = host.idle.len();
+            // dropping the stream deregisters it from the Reactor
+            host.idle.retain(|idle| idle.since.elapsed() < self.idle_timeout);
+            let closed = before - host.idle.len();
+            if closed > 0 {
+                host.open -= closed;
+                wake_all(host);     // the freed slots may let waiters connect
+            }
+            host.open > 0 || !host.waiters.is_empty()
+        });
+    }
+}
+
+// a waiter may belong to a future that was dropped in the meantime, so everyone gets a chance to check out
+fn wake_all(host: &mut Host) {
+    host.waiters.drain(..).for_each(|w| w.wake());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_task_is_queued_once() {
+        let pool = Pool::new(0, IDLE_TIMEOUT);
+        let wakers = Waker::detached(2);
+        for _ in 0..3 {
+            assert!(matches!(pool.checkout("a:80", &wakers[0]), Ok(Checkout::Wait)));
+        }
+        assert!(matches!(pool.checkout("a:80", &wakers[1]), Ok(Checkout::Wait)));
+        assert_eq!(pool.hosts.lock().unwrap()["a:80"].waiters.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn expired_connections_of_other_hosts_are_closed() {
+        use crate::{net::AsyncUnixStream, runtime::Runtime};
+
+        let rt = Runtime::with_workers(1);
+        let _context = rt.enter();
+        let pool = Pool::new(1, Duration::ZERO);
+        let waker = &Waker::detached(1)[0];
+
+        assert!(matches!(pool.checkout("a:80", waker), Ok(Checkout::Connect)));
+        let (stream, _peer) = AsyncUnixStream::pair().unwrap();
+        pool.checkin(Connection::new("a:80", Transport::Unix(stream)));
+        assert_eq!(rt.reactor().live_registrations(), 2);
+
+        assert!(matches!(pool.checkout("b:80", waker), Ok(Checkout::Connect)));
+        assert_eq!(rt.reactor().live_registrations(), 1);
+        assert!(!pool.hosts.lock().unwrap().contains_key("a:80"));
+    }
+
+    #[test]
+    fn shutdown_wakes_waiters_and_fails_checkout() {
+        let pool = Pool::new(0, IDLE_TIMEOUT);
+        let wakers = Waker::detached(2);
+        assert!(matches!(pool.checkout("a:80", &wakers[0]), Ok(Checkout::Wait)));
+        assert!(matches!(pool.checkout("b:80", &wakers[1]), Ok(Checkout::Wait)));
+
+        pool.shutdown();
+        let mut woken = wakers[0].woken();
+        woken.sort();
+        assert_eq!(woken, [0, 1]);
+        assert!(pool.checkout("a:80", &wakers[0]).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use super::{HttpError, Url};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+    Custom(String),         // any other method token, sent as it is
+}
+
+impl Method {
+    // repeating an idempotent request has the same effect as sending it once
+    pub fn is_idempotent(&self) -> bool {
+        matches!(self, Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+            Method::Custom(method) => method,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/*
+Request:
+    - builder for a request with any method, extra headers and a body
+    - `Host`, `Connection` and `Content-Length` are filled in when serialized unless set explicitly
+    - an invalid URL, or a method or header that would break the request's framing (a CR/LF smuggling in
+      another header, say), is reported by the future sending the request
+    - `unix_socket` sends it over a Unix socket instead; the URL still gives the path and `Host` header
+ */
+pub struct Request {
+    method: Method,
+    url: Result<Url, HttpError>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    unix_socket: Option<PathBuf>,
+}
+
+impl Request {
+    pub fn new(method: Method, url: &str) -> Self {
+        Self::with_url(method, Url::parse(url))
+    }
+
+    pub fn get(url: &str) -> Self {
+        Self::new(Method::Get, url)
+    }
+
+    pub fn post(url: &str) -> Self {
+        Self::new(Method::Post, url)
+    }
+
+    pub fn put(url: &str) -> Self {
+        Self::new(Method::Put, url)
+    }
+
+    pub fn delete(url: &str) -> Self {
+        Self::new(Method::Delete, url)
+    }
+
+    pub(super) fn with_url(method: Method, url: Result<Url, HttpError>) -> Self {
+        Self { method, url, headers: vec![], body: vec![], unix_socket: None }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    // e.g. `Request::get("http://localhost/status").unix_socket("/run/sidecar.sock")`
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: impl AsRef<Path>) -> Self {
+        self.unix_socket = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    fn has_header(&self, name: &str) -> bool {
+        self.headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name))
+    }
+
+    // socket to connect to instead of the URL's host
+    pub(super) fn socket_path(&self) -> Option<&Path> {
+        self.unix_socket.as_deref()
+    }
+
+    // the method and header names must be tokens, header values must not contain line breaks, and the
+    // request target and `Host` value must not contain spaces or control characters
+    fn check(&self) -> Result<(), HttpError> {
+        if !is_token(self.method.as_str()) {
+            return Err(HttpError::InvalidRequest(format!("method {:?} is not a token", self.method.as_str())));
+        }
+        if let Ok(url) = &self.url {
+            let unsafe_byte = |b: u8| b == b' ' || b.is_ascii_control();
+            if url.path().bytes().any(unsafe_byte) {
+                let reason = format!("request target {:?} contains a space or control character", url.path());
+                return Err(HttpError::InvalidRequest(reason));
+            }
+            if url.authority().bytes().any(unsafe_byte) {
+                let reason = format!("host {:?} contains a space or control character", url.authority());
+                return Err(HttpError::InvalidRequest(reason));
+            }
+        }
+        for (name, value) in &self.headers {
+            if !is_token(name) {
+                return Err(HttpError::InvalidRequest(format!("header name {name:?} is not a token")));
+            }
+            if value.bytes().any(|b| matches!(b, b'\r' | b'\n' | b'\0')) {
+                return Err(HttpError::InvalidRequest(format!("value of header {name:?} contains a line break")));
+            }
+        }
+        Ok(())
+    }
+
+    // splits the request into its URL, method and the bytes to write to the socket
+    pub(super) fn encode(self) -> (Result<Url, HttpError>, Method, Vec<u8>) {
+        if let Err(e) = self.check() {
+            return (Err(e), self.method, vec![]);
+        }
+        let Ok(url) = &self.url else {
+            return (self.url, self.method, vec![]);
+        };
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", self.method, url.path());
+        if !self.has_header("host") {
+            head.push_str(&format!("Host: {}\r\n", url.authority()));
+        }
+        if !self.has_header("connection") {
+            head.push_str("Connection: keep-alive\r\n");
+        }
+        // a body needs its length; POST/PUT/PATCH announce an empty body explicitly
+        let needs_length = !self.body.is_empty() || matches!(self.method, Method::Post | Method::Put | Method::Patch);
+        if needs_length && !self.has_header("content-length") {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        (self.url, self.method, bytes)
+    }
+}
+
+// token characters of RFC 9110, section 5.6.2
+fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(request: Request) -> String {
+        String::from_utf8(request.encode().2).unwrap()
+    }
+
+    #[test]
+    fn default_headers() {
+        assert_eq!(
+            encoded(Request::get("http://localhost:8080/a?b=1")),
+            "GET /a?b=1 HTTP/1.1\r\nHost: localhost:8080\r\nConnection: keep-alive\r\n\r\n",
+        );
+        assert_eq!(
+            encoded(Request::post("http://localhost/").body("hi")),
+            "POST / HTTP/1.1\r\nHost: localhost\r\nConnection: keep-alive\r\nContent-Length: 2\r\n\r\nhi",
+        );
+        assert!(encoded(Request::put("http://localhost/")).contains("Content-Length: 0\r\n"));
+        assert!(!encoded(Request::delete("http://localhost/")).contains("Content-Length"));
+    }
+
+    #[test]
+    fn explicit_headers_replace_defaults() {
+        let request = Request::new(Method::Patch, "http://localhost/")
+            .header("host", "example.com")
+            .header("Connection", "close")
+            .header("X-Id", "7")
+            .body("{}");
+        assert_eq!(
+            encoded(request),
+            "PATCH / HTTP/1.1\r\nContent-Length: 2\r\nhost: example.com\r\nConnection: close\r\nX-Id: 7\r\n\r\n{}",
+        );
+    }
+
+    #[test]
+    fn invalid_url_is_passed_on() {
+        let (url, method, bytes) = Request::new(Method::Head, "localhost").encode();
+        assert!(matches!(url, Err(HttpError::InvalidUrl(_))));
+        assert_eq!(method, Method::Head);
+        assert!(bytes.is_empty());
+    }
+
+    fn rejected(request: Request) -> bool {
+        matches!(request.encode().0, Err(HttpError::InvalidRequest(_)))
+    }
+
+    #[test]
+    fn line_breaks_in_headers_are_rejected() {
+        assert!(rejected(Request::get("http://localhost/").header("X-Id", "1\r\nX-Admin: true")));
+        assert!(rejected(Request::get("http://localhost/").header("X-Id", "1\n")));
+        assert!(rejected(Request::get("http://localhost/").header("X-Id\r\nX-Admin", "true")));
+        assert!(rejected(Request::get("http://localhost/").header("X Id", "1")));
+        assert!(rejected(Request::get("http://localhost/").header("", "1")));
+        assert!(!rejected(Request::get("http://localhost/").header("X-Id", "a b\tc")));
+
+        // `Url::parse` refuses these already; `check` doesn't rely on it
+        let target = Url::unchecked("localhost", 80, "/x HTTP/1.1\r\nX-Injected: yes\r\n\r\nGET /y");
+        assert!(rejected(Request::with_url(Method::Get, Ok(target))));
+        assert!(rejected(Request::with_url(Method::Get, Ok(Url::unchecked("localhost", 80, "/a b")))));
+        assert!(rejected(Request::with_url(Method::Get, Ok(Url::unchecked("localhost", 80, "/\0")))));
+        let host = Url::unchecked("local\r\nX-Injected: yes", 80, "/");
+        assert!(rejected(Request::with_url(Method::Get, Ok(host))));
+        assert!(rejected(Request::with_url(Method::Get, Ok(Url::unchecked("local host", 80, "/")))));
+        // and a URL that never got that far still isn't sent
+        assert!(Request::get("http://127.0.0.1:8080/x HTTP/1.1\r\nX-Injected: yes\r\n\r\nGET /y").encode().0.is_err());
+    }
+
+    #[test]
+    fn custom_method_must_be_a_token() {
+        assert!(rejected(Request::new(Method::Custom("GET / HTTP/1.1\r\n".into()), "http://localhost/")));
+        assert!(rejected(Request::new(Method::Custom(String::new()), "http://localhost/")));
+        assert!(!rejected(Request::new(Method::Custom("PURGE".into()), "http://localhost/")));
+    }
+}
@@ -0,0 +1,395 @@
+use super::HttpError;
+use std::{borrow::Cow, collections::HashMap};
+
+/*
+Response:
+    - a parsed HTTP response
+    - header names are stored lowercase, so lookups with `header` are case-insensitive;
+      repeated headers are joined with ", "
+ */
+#[derive(Debug, Clone)]
+pub struct Response {
+    status: u16,
+    reason: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn into_body(self) -> Vec<u8> {
+        self.body
+    }
+
+    // body as text; invalid UTF-8 sequences are replaced
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+}
+
+// status line and headers, available as soon as the blank line ending the head has arrived
+struct Head {
+    status: u16,
+    reason: String,
+    headers: HashMap<String, String>,
+    keep_alive: bool,       // the server leaves the connection open after this response
+}
+
+// how the end of the body is recognized
+enum BodyKind {
+    UntilClose,             // neither Content-Length nor chunked; the server closes the connection
+    Length(usize),          // Content-Length; counts down the bytes still to come
+    Chunked(ChunkState),    // Transfer-Encoding: chunked
+}
+
+enum ChunkState {
+    Size,                   // waiting for a `<hex size>[;extensions]\r\n` line
+    Data(usize),            // bytes left in the current chunk
+    DataEnd,                // waiting for the `\r\n` after the chunk data
+    Trailers,               // after the last chunk; skipping trailer lines until an empty one
+}
+
+/*
+ResponseParser:
+    - fed with the bytes read from the socket as they arrive
+    - parses the status line and headers as soon as the whole head is buffered
+    - then decodes the body according to the head: `Content-Length`, `Transfer-Encoding: chunked`,
+      or everything until the server closes the connection
+    - `is_complete` tells when the body is finished, so the caller doesn't have to wait for EOF
+ */
+pub struct ResponseParser {
+    buffer: Vec<u8>,        // received bytes that aren't parsed yet
+    expect_body: bool,      // false for responses to HEAD requests, which announce a body but never send it
+    head: Option<Head>,
+    kind: BodyKind,
+    body: Vec<u8>,
+    complete: bool,
+}
+
+impl ResponseParser {
+    pub fn new(expect_body: bool) -> Self {
+        Self { buffer: vec![], expect_body, head: None, kind: BodyKind::UntilClose, body: vec![], complete: false }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        self.buffer.extend_from_slice(data);
+
+        if self.head.is_none() {
+            let Some(end) = find(&self.buffer, b"\r\n\r\n") else {
+                return Ok(());      // head not complete yet
+            };
+            let head = parse_head(&self.buffer[..end])?;
+            self.buffer.drain(..end + 4);
+            self.kind = if self.expect_body { body_kind(&head)? } else { BodyKind::Length(0) };
+            self.head = Some(head);
+        }
+
+        self.decode_body()
+    }
+
+    // true once the whole body has arrived
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    // status and headers as a Response without body, once the head has arrived
+    pub fn head(&self) -> Option<Response> {
+        self.head.as_ref().map(|head| Response {
+            status: head.status,
+            reason: head.reason.clone(),
+            headers: head.headers.clone(),
+            body: vec![],
+        })
+    }
+
+    // body bytes decoded so far; they're not part of the Response returned by `finish`
+    pub fn take_body(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.body)
+    }
+
+    // true as long as no byte of the response has arrived
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none() && self.buffer.is_empty()
+    }
+
+    // the connection can carry another request: the body ended on its own (not by closing the connection),
+    // the server didn't ask to close it, and nothing beyond this response was received
+    pub fn is_reusable(&self) -> bool {
+        self.complete
+            && self.buffer.is_empty()
+            && !matches!(self.kind, BodyKind::UntilClose)
+            && self.head.as_ref().is_some_and(|h| h.keep_alive)
+    }
+
+    fn decode_body(&mut self) -> Result<(), HttpError> {
+        match &mut self.kind {
+            BodyKind::UntilClose => self.body.append(&mut self.buffer),
+            BodyKind::Length(left) => {
+                let n = (*left).min(self.buffer.len());
+                self.body.extend(self.buffer.drain(..n));
+                *left -= n;
+                self.complete = *left == 0;
+            }
+            BodyKind::Chunked(state) => loop {
+                match state {
+                    ChunkState::Size => {
+                        let Some(end) = find(&self.buffer, b"\r\n") else { break };
+                        let line = String::from_utf8_lossy(&self.buffer[..end]).to_string();
+                        let size = line.split(';').next().unwrap_or_default().trim();
+                        let size = usize::from_str_radix(size, 16)
+                            .map_err(|_| HttpError::MalformedResponse(format!("invalid chunk size {line:?}")))?;
+                        self.buffer.drain(..end + 2);
+                        *state = if size == 0 { ChunkState::Trailers } else { ChunkState::Data(size) };
+                    }
+                    ChunkState::Data(left) => {
+                        if self.buffer.is_empty() {
+                            break;
+                        }
+                        let n = (*left).min(self.buffer.len());
+                        self.body.extend(self.buffer.drain(..n));
+                        *left -= n;
+                        if *left == 0 {
+                            *state = ChunkState::DataEnd;
+                        }
+                    }
+                    ChunkState::DataEnd => {
+                        if self.buffer.len() < 2 {
+                            break;
+                        }
+                        if !self.buffer.starts_with(b"\r\n") {
+                            return Err(HttpError::MalformedResponse("missing CRLF after chunk data".into()));
+                        }
+                        self.buffer.drain(..2);
+                        *state = ChunkState::Size;
+                    }
+                    ChunkState::Trailers => {
+                        let Some(end) = find(&self.buffer, b"\r\n") else { break };
+                        self.buffer.drain(..end + 2);
+                        if end == 0 {
+                            self.complete = true;   // empty line ends the chunked body
+                            break;
+                        }
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // called once the body is complete or the server closed the connection
+    pub fn finish(self) -> Result<Response, HttpError> {
+        let head = self
+            .head
+            .ok_or_else(|| HttpError::MalformedResponse("connection closed before the headers were complete".into()))?;
+        if !self.complete && !matches!(self.kind, BodyKind::UntilClose) {
+            return Err(HttpError::MalformedResponse("connection closed before the body was complete".into()));
+        }
+        Ok(Response {
+            status: head.status,
+            reason: head.reason,
+            headers: head.headers,
+            body: self.body,
+        })
+    }
+}
+
+// chunked wins over Content-Length; responses that never have a body are complete right after the head
+fn body_kind(head: &Head) -> Result<BodyKind, HttpError> {
+    if (100..200).contains(&head.status) || head.status == 204 || head.status == 304 {
+        return Ok(BodyKind::Length(0));
+    }
+    if let Some(encoding) = head.headers.get("transfer-encoding") {
+        if encoding.to_ascii_lowercase().split(',').any(|e| e.trim() == "chunked") {
+            return Ok(BodyKind::Chunked(ChunkState::Size));
+        }
+    }
+    match head.headers.get("content-length") {
+        Some(len) => len
+            .trim()
+            .parse()
+            .map(BodyKind::Length)
+            .map_err(|_| HttpError::MalformedResponse(format!("invalid Content-Length {len:?}"))),
+        None => Ok(BodyKind::UntilClose),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// `HTTP/1.1 200 OK` followed by `Name: value` lines
+fn parse_head(head: &[u8]) -> Result<Head, HttpError> {
+    let head = std::str::from_utf8(head)
+        .map_err(|_| HttpError::MalformedResponse("response head is not valid UTF-8".into()))?;
+    let mut lines = head.split("\r\n");
+
+    let status_line = lines.next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    let (version, status, reason) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(version), Some(code), reason) if version.starts_with("HTTP/") && code.len() == 3 => {
+            let status = code
+                .parse()
+                .map_err(|_| HttpError::MalformedResponse(format!("invalid status line {status_line:?}")))?;
+            (version, status, reason.unwrap_or_default().to_string())
+        }
+        _ => return Err(HttpError::MalformedResponse(format!("invalid status line {status_line:?}"))),
+    };
+
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| HttpError::MalformedResponse(format!("invalid header line {line:?}")))?;
+        let (name, value) = (name.trim().to_ascii_lowercase(), value.trim());
+        headers
+            .entry(name)
+            .and_modify(|v| {
+                v.push_str(", ");
+                v.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    // HTTP/1.1 keeps the connection open unless told otherwise; HTTP/1.0 closes it unless told otherwise
+    let connection = headers.get("connection").map(|c| c.to_ascii_lowercase()).unwrap_or_default();
+    let keep_alive = match version {
+        "HTTP/1.0" => connection.contains("keep-alive"),
+        _ => !connection.contains("close"),
+    };
+
+    Ok(Head { status, reason, headers, keep_alive })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // feeds `data` in pieces of `step` bytes, like reads returning short
+    fn parse(data: &[u8], step: usize) -> ResponseParser {
+        let mut parser = ResponseParser::new(true);
+        data.chunks(step).for_each(|chunk| parser.feed(chunk).unwrap());
+        parser
+    }
+
+    #[test]
+    fn content_length_body() {
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-A: 1\r\nx-a: 2\r\n\r\nhello";
+        for step in [1, 7, data.len()] {
+            let parser = parse(data, step);
+            assert!(parser.is_complete() && parser.is_reusable());
+            let response = parser.finish().unwrap();
+            assert_eq!((response.status(), response.reason()), (200, "OK"));
+            assert_eq!(response.header("x-a"), Some("1, 2"));
+            assert_eq!(response.header("CONTENT-LENGTH"), Some("5"));
+            assert_eq!(response.body(), b"hello");
+        }
+    }
+
+    #[test]
+    fn content_length_incomplete() {
+        let parser = parse(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhello", 4);
+        assert!(!parser.is_complete());
+        assert!(matches!(parser.finish(), Err(HttpError::MalformedResponse(_))));
+    }
+
+    #[test]
+    fn chunked_body() {
+        let data = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                     5;ext=1\r\nhello\r\n7\r\n, world\r\n0\r\nTrailer: x\r\n\r\n";
+        for step in [1, 3, data.len()] {
+            let parser = parse(data, step);
+            assert!(parser.is_complete() && parser.is_reusable());
+            assert_eq!(parser.finish().unwrap().text(), "hello, world");
+        }
+    }
+
+    #[test]
+    fn chunked_wins_over_content_length() {
+        let parser = parse(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nab\r\n0\r\n\r\n", 5);
+        assert_eq!(parser.finish().unwrap().body(), b"ab");
+    }
+
+    #[test]
+    fn malformed_chunks() {
+        let mut parser = ResponseParser::new(true);
+        let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        assert!(matches!(parser.feed(&[&head[..], b"zz\r\n"].concat()), Err(HttpError::MalformedResponse(_))));
+
+        let mut parser = ResponseParser::new(true);
+        assert!(matches!(parser.feed(&[&head[..], b"2\r\nabXY"].concat()), Err(HttpError::MalformedResponse(_))));
+    }
+
+    #[test]
+    fn body_until_close() {
+        let mut parser = parse(b"HTTP/1.0 200 OK\r\n\r\npart one, ", 4);
+        parser.feed(b"part two").unwrap();
+        assert!(!parser.is_complete() && !parser.is_reusable());
+        assert_eq!(parser.finish().unwrap().text(), "part one, part two");
+    }
+
+    #[test]
+    fn responses_without_body() {
+        let parser = parse(b"HTTP/1.1 204 No Content\r\n\r\n", 100);
+        assert!(parser.is_complete());
+
+        let mut parser = ResponseParser::new(false);     // response to HEAD
+        parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n").unwrap();
+        assert!(parser.is_complete() && parser.is_reusable());
+    }
+
+    #[test]
+    fn connection_reuse() {
+        assert!(!parse(b"HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n", 100).is_reusable());
+        assert!(!parse(b"HTTP/1.0 200 OK\r\nContent-Length: 0\r\n\r\n", 100).is_reusable());
+        assert!(parse(b"HTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 0\r\n\r\n", 100).is_reusable());
+        // extra bytes after the response
+        assert!(!parse(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\nHTTP", 100).is_reusable());
+    }
+
+    #[test]
+    fn head_and_streamed_body() {
+        let mut parser = ResponseParser::new(true);
+        assert!(parser.is_empty() && parser.head().is_none());
+        parser.feed(b"HTTP/1.1 404 Not Found\r\nContent-Length: 6\r\n\r\nabc").unwrap();
+        assert_eq!(parser.head().unwrap().status(), 404);
+        assert_eq!(parser.take_body(), b"abc");
+        parser.feed(b"def").unwrap();
+        assert_eq!(parser.take_body(), b"def");
+        assert!(parser.finish().unwrap().body().is_empty());
+    }
+
+    #[test]
+    fn invalid_heads() {
+        for head in [&b"HTP/1.1 200 OK\r\n\r\n"[..], b"HTTP/1.1 20 OK\r\n\r\n", b"HTTP/1.1 200 OK\r\nbroken\r\n\r\n"] {
+            assert!(matches!(ResponseParser::new(true).feed(head), Err(HttpError::MalformedResponse(_))));
+        }
+        let mut parser = ResponseParser::new(true);
+        assert!(matches!(
+            parser.feed(b"HTTP/1.1 200 OK\r\nContent-Length: ten\r\n\r\n"),
+            Err(HttpError::MalformedResponse(_))
+        ));
+    }
+}
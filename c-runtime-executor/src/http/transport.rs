@@ -0,0 +1,65 @@
+use crate::{
+    future::PollState,
+    io::{AsyncRead, AsyncWrite},
+    net::AsyncTcpStream,
+    runtime::Waker,
+};
+#[cfg(unix)]
+use crate::net::AsyncUnixStream;
+use std::io::{self, ErrorKind};
+
+// what a pooled connection talks over: TCP to the URL's host, or a Unix socket set with `Request::unix_socket`
+pub enum Transport {
+    Tcp(AsyncTcpStream),
+    #[cfg(unix)]
+    Unix(AsyncUnixStream),
+}
+
+impl Transport {
+    pub fn poll_connected(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        match self {
+            Transport::Tcp(stream) => stream.poll_connected(waker),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.poll_connected(waker),
+        }
+    }
+
+    pub fn clear_waker(&self) {
+        match self {
+            Transport::Tcp(stream) => stream.clear_waker(),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.clear_waker(),
+        }
+    }
+
+    // an idle connection must have nothing to read; EOF or unexpected data means it can't be reused
+    pub fn is_alive(&mut self) -> bool {
+        let result = match self {
+            Transport::Tcp(stream) => stream.peek(&mut [0u8; 1]),
+            // no peek on Unix sockets; reading is fine, since a connection with data to read is closed anyway
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.try_read(&mut [0u8; 1]),
+        };
+        matches!(result, Err(e) if e.kind() == ErrorKind::WouldBlock)
+    }
+}
+
+impl AsyncRead for Transport {
+    fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>> {
+        match self {
+            Transport::Tcp(stream) => stream.poll_read(waker, buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.poll_read(waker, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Transport {
+    fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>> {
+        match self {
+            Transport::Tcp(stream) => stream.poll_write(waker, buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.poll_write(waker, buf),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{future::PollState, runtime::Waker, Future};
+use std::io::{self, ErrorKind};
+
+/*
+AsyncRead / AsyncWrite:
+    - non-blocking counterparts of std::io::Read / Write for sources registered with the Reactor
+    - NotReady means the source would block; the implementation has stored the Waker and it's woken on
+      the next readiness event, so callers never deal with WouldBlock or Interrupted themselves
+    - Ready(Ok(0)) from `poll_read` means EOF
+ */
+pub trait AsyncRead {
+    fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>>;
+
+    // future reading until EOF, appending to `buf`; resolves to the number of bytes read
+    fn read_to_end<'a>(&'a mut self, buf: &'a mut Vec<u8>) -> ReadToEnd<'a, Self>
+    where
+        Self: Sized,
+    {
+        ReadToEnd { reader: self, buf, read: 0 }
+    }
+}
+
+pub trait AsyncWrite {
+    fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>>;
+
+    // future writing all of `buf`
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> WriteAll<'a, Self>
+    where
+        Self: Sized,
+    {
+        WriteAll { writer: self, buf }
+    }
+}
+
+pub struct ReadToEnd<'a, R> {
+    reader: &'a mut R,
+    buf: &'a mut Vec<u8>,
+    read: usize,
+}
+
+impl<R: AsyncRead> Future for ReadToEnd<'_, R> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.reader.poll_read(waker, &mut chunk) {
+                PollState::Ready(Ok(0)) => break PollState::Ready(Ok(self.read)),
+                PollState::Ready(Ok(n)) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.read += n;
+                }
+                PollState::Ready(Err(e)) => break PollState::Ready(Err(e)),
+                PollState::NotReady => break PollState::NotReady,
+            }
+        }
+    }
+}
+
+pub struct WriteAll<'a, W> {
+    writer: &'a mut W,
+    buf: &'a [u8],          // what's left to write
+}
+
+impl<W: AsyncWrite> Future for WriteAll<'_, W> {
+    type Output = io::Result<()>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        while !self.buf.is_empty() {
+            match self.writer.poll_write(waker, self.buf) {
+                PollState::Ready(Ok(0)) => return PollState::Ready(Err(ErrorKind::WriteZero.into())),
+                PollState::Ready(Ok(n)) => self.buf = &self.buf[n..],
+                PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,10 @@
+pub mod future;
+pub mod http;
+pub mod io;
+pub mod net;
+#[cfg(unix)]
+pub mod pipe;
+pub mod runtime;
+pub mod time;
+
+pub use future::{Future, PollState};
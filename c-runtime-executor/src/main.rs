@@ -1,30 +1,19 @@
-mod future;
-mod http;
-mod runtime;
-use crate::http::Http;
-use future::{Future, PollState};
-use runtime::{Executor, Waker};
-use std::thread::Builder;
-
+use c_runtime_executor::{
+    future::join_all,
+    http::{Http, HttpError, Response},
+    runtime::{self, Waker},
+    Future, PollState,
+};
+
+// expects a delay server on 127.0.0.1:8080; start one with `cargo run --bin delayserver`
 fn main() {
-    let mut executor = runtime::init();
-    let mut handles = vec![];
+    let mut rt = runtime::init();    // the Reactor is shut down when `rt` is dropped
 
     use std::time::Instant;
     let now = Instant::now();
 
-    // Runs on 12 cores
-    for i in 1..12 {
-        let name = format!("exec-{i}");
-        let h = Builder::new().name(name).spawn(move || {
-            let mut executor = Executor::new();
-            executor.block_on(async_main());
-        }).unwrap();
-        handles.push(h);
-    }
-
-    executor.block_on(async_main());
-    handles.into_iter().for_each(|h| h.join().unwrap());
+    // one block_on; the requests are spread over a worker thread per core
+    rt.block_on(async_main());
 
     let elapsed = now.elapsed();
     println!("Time running the program: {:?}", elapsed);
@@ -38,23 +27,24 @@ fn main() {
 // =================================
     
 // coroutine fn request(i: usize) {
-//     let path = format!("/{}/HelloWorld{i}", i * 1000);
-//     let txt = Http::get(&path).wait;
-//     let txt = txt.lines().last().unwrap_or_default();
-//     println!(«{txt}»)
+//     let url = format!("http://127.0.0.1:8080/{}/HelloWorld{i}", i * 1000);
+//     match Http::get(&url).wait {
+//         Ok(response) => println!("{}", response.text()),
+//         Err(e) => println!("request failed: {e}"),
+//     }
 // }
 
 // =================================
 // Into this:
 // =================================
 
-fn request(i: usize) -> impl Future<Output=String> {
+fn request(i: usize) -> impl Future<Output=()> {
     Coroutine0::new(i)
 }
         
 enum State0 {
     Start(usize),
-    Wait1(Box<dyn Future<Output = String>>),
+    Wait1(Box<dyn Future<Output = Result<Response, HttpError>> + Send>),
     Resolved,
 }
 
@@ -70,17 +60,17 @@ impl Coroutine0 {
 
 
 impl Future for Coroutine0 {
-    type Output = String;
+    type Output = ();
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
             match self.state {
                 State0::Start(i) => {
                     // ---- Code you actually wrote ----
-                    let path = format!("/{}/HelloWorld{i}", i * 1000);
+                    let url = format!("http://127.0.0.1:8080/{}/HelloWorld{i}", i * 1000);
 
                     // ---------------------------------
-                    let fut1 = Box::new( Http::get(&path));
+                    let fut1 = Box::new( Http::get(&url));
                     self.state = State0::Wait1(fut1);
                 }
 
@@ -88,12 +78,14 @@ impl Future for Coroutine0 {
                     match f1.poll(waker) {
                         PollState::Ready(txt) => {
                             // ---- Code you actually wrote ----
-                            let txt = txt.lines().last().unwrap_or_default();
-                            println!("{txt}");
+                            match txt {
+                                Ok(response) => println!("{}", response.text()),
+                                Err(e) => println!("request failed: {e}"),
+                            }
 
                             // ---------------------------------
                             self.state = State0::Resolved;
-                            break PollState::Ready(String::new());
+                            break PollState::Ready(());
                         }
                         PollState::NotReady => break PollState::NotReady,
                     }
@@ -112,24 +104,28 @@ impl Future for Coroutine0 {
     
 // coroutine fn async_main() {
 //     println!("Program starting");
-//     
-//     for i in 0..5 {
-//         let future = request(i);
-//         runtime::spawn(future);
+//     let mut handles = vec![];
+//
+//     for i in 0..60 {
+//         let future = request(i % 5);
+//         handles.push(runtime::spawn(future));
 //     }
-
+//
+//     join_all(handles).wait;
+//     println!("All requests finished");
 // }
 
 // =================================
 // Into this:
 // =================================
 
-fn async_main() -> impl Future<Output=String> {
+fn async_main() -> impl Future<Output=()> {
     Coroutine1::new()
 }
         
 enum State1 {
     Start,
+    Wait1(Box<dyn Future<Output = String> + Send>),
     Resolved,
 }
 
@@ -145,7 +141,7 @@ impl Coroutine1 {
 
 
 impl Future for Coroutine1 {
-    type Output = String;
+    type Output = ();
 
     fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
         loop {
@@ -153,15 +149,30 @@ impl Future for Coroutine1 {
                 State1::Start => {
                     // ---- Code you actually wrote ----
                     println!("Program starting");
-    
-                    for i in 0..5 {
-                        let future = request(i);
-                        runtime::spawn(future);
+                    let mut handles = vec![];
+
+                    for i in 0..60 {
+                        let future = request(i % 5);
+                        handles.push(runtime::spawn(future));
                     }
 
                     // ---------------------------------
-                    self.state = State1::Resolved;
-                    break PollState::Ready(String::new());
+                    let fut1 = Box::new(join_all(handles));
+                    self.state = State1::Wait1(fut1);
+                }
+
+                State1::Wait1(ref mut f1) => {
+                    match f1.poll(waker) {
+                        PollState::Ready(_) => {
+                            // ---- Code you actually wrote ----
+                            println!("All requests finished");
+
+                            // ---------------------------------
+                            self.state = State1::Resolved;
+                            break PollState::Ready(());
+                        }
+                        PollState::NotReady => break PollState::NotReady,
+                    }
                 }
 
                 State1::Resolved => panic!("Polled a resolved future")
@@ -1,40 +1,33 @@
-mod future;
-mod http;
-mod runtime;
-use crate::http::Http;
-use future::{Future, PollState};
-use runtime::{Executor, Waker};
-use std::thread::Builder;
+use c_runtime_executor::{
+    future::join_all,
+    http::{Http, HttpError, Response},
+    runtime::{self, Waker},
+    Future, PollState,
+};
 
+// expects a delay server on 127.0.0.1:8080; start one with `cargo run --bin delayserver`
 fn main() {
-    let mut executor = runtime::init();
-    let mut handles = vec![];
-
-    for i in 1..12 {
-        let name = format!("exec-{i}");
-        let h = Builder::new().name(name).spawn(move || {
-            let mut executor = Executor::new();
-            executor.block_on(async_main());
-        }).unwrap();
-        handles.push(h);
-    }
-
-    executor.block_on(async_main());
-    handles.into_iter().for_each(|h| h.join().unwrap());
+    let mut rt = runtime::init();    // the Reactor is shut down when `rt` is dropped
+    rt.block_on(async_main());
 }
 
 coroutine fn request(i: usize) {
-    let path = format!("/{}/HelloWorld{i}", i * 1000);
-    let txt = Http::get(&path).wait;
-    let txt = txt.lines().last().unwrap_or_default();
-    println!("{txt}");
+    let url = format!("http://127.0.0.1:8080/{}/HelloWorld{i}", i * 1000);
+    match Http::get(&url).wait {
+        Ok(response) => println!("{}", response.text()),
+        Err(e) => println!("request failed: {e}"),
+    }
 }
 
 coroutine fn async_main() {
     println!("Program starting");
+    let mut handles = vec![];
 
-    for i in 0..5 {
-        let future = request(i);
-        runtime::spawn(future);
+    for i in 0..60 {
+        let future = request(i % 5);
+        handles.push(runtime::spawn(future));
     }
+
+    join_all(handles).wait;
+    println!("All requests finished");
 }
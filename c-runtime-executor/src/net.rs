@@ -0,0 +1,298 @@
+use crate::{
+    future::{PollState, Stream},
+    io::{AsyncRead, AsyncWrite},
+    runtime::{Registration, Waker},
+    Future,
+};
+use mio::{net::TcpStream, Interest};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, ToSocketAddrs},
+};
+
+#[cfg(unix)]
+pub use unix::{AsyncUnixListener, AsyncUnixStream, UnixAccept};
+
+#[cfg(unix)]
+mod unix;
+
+// the first address `addr` resolves to, e.g. "127.0.0.1:8080"
+fn first_addr(addr: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "no address to bind to"))
+}
+
+/*
+AsyncTcpStream:
+    - a mio TcpStream registered with the Reactor once, for both READABLE and WRITABLE, under its own id
+    - every operation that would block stores the Waker under that id, so the task is woken by the next event
+    - dropping the stream deregisters it
+ */
+pub struct AsyncTcpStream {
+    stream: TcpStream,
+    registration: Registration,
+}
+
+impl AsyncTcpStream {
+    // starts a non-blocking connect; wait for `poll_connected` before reading or writing
+    pub fn connect(addr: SocketAddr) -> io::Result<AsyncTcpStream> {
+        AsyncTcpStream::from_mio(TcpStream::connect(addr)?)
+    }
+
+    // registers an already connected (or connecting) non-blocking stream with the Reactor
+    pub fn from_mio(mut stream: TcpStream) -> io::Result<AsyncTcpStream> {
+        let registration = Registration::new(&mut stream, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(AsyncTcpStream { stream, registration })
+    }
+
+    // Ready(Ok(())) once a connect started by `connect` has finished
+    pub fn poll_connected(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        self.registration.poll_connected(waker, || self.stream.take_error(), || self.stream.peer_addr())
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.local_addr()
+    }
+
+    // reads without consuming; WouldBlock when there's nothing to read
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.peek(buf)
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    // stops waking the last task that waited on this stream, e.g. when it's put aside for later reuse
+    pub fn clear_waker(&self) {
+        self.registration.clear_waker();
+    }
+}
+
+impl AsyncRead for AsyncTcpStream {
+    fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.stream.read(buf))
+    }
+}
+
+impl AsyncWrite for AsyncTcpStream {
+    fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.stream.write(buf))
+    }
+}
+
+impl Drop for AsyncTcpStream {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.stream);
+    }
+}
+
+/*
+TcpListener:
+    - listening socket registered with the Reactor for READABLE when it's bound
+    - `accept` resolves to the next incoming connection; `incoming` turns the listener into a Stream of them
+    - accepted streams are registered with the Reactor, ready to read and write
+    - dropping the listener deregisters it
+ */
+pub struct TcpListener {
+    listener: mio::net::TcpListener,
+    registration: Registration,
+}
+
+impl TcpListener {
+    // binds to the first address `addr` resolves to, e.g. "127.0.0.1:8080"
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+        let mut listener = mio::net::TcpListener::bind(first_addr(addr)?)?;
+        let registration = Registration::new(&mut listener, Interest::READABLE)?;
+        Ok(TcpListener { listener, registration })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn poll_accept(&mut self, waker: &Waker) -> PollState<io::Result<(AsyncTcpStream, SocketAddr)>> {
+        self.registration.poll_io(waker, || {
+            let (stream, addr) = self.listener.accept()?;
+            Ok((AsyncTcpStream::from_mio(stream)?, addr))
+        })
+    }
+
+    pub fn accept(&mut self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+
+    // owning Stream of incoming connections, so it can be kept in a coroutine state
+    pub fn incoming(self) -> Incoming {
+        Incoming { listener: self }
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.listener);
+    }
+}
+
+pub struct Accept<'a> {
+    listener: &'a mut TcpListener,
+}
+
+impl Future for Accept<'_> {
+    type Output = io::Result<(AsyncTcpStream, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.listener.poll_accept(waker)
+    }
+}
+
+// never finishes; an error accepting one connection is yielded as an item
+pub struct Incoming {
+    listener: TcpListener,
+}
+
+impl Stream for Incoming {
+    type Item = io::Result<(AsyncTcpStream, SocketAddr)>;
+
+    fn poll_next(&mut self, waker: &Waker) -> PollState<Option<Self::Item>> {
+        match self.listener.poll_accept(waker) {
+            PollState::Ready(conn) => PollState::Ready(Some(conn)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+/*
+AsyncUdpSocket:
+    - a mio UdpSocket registered with the Reactor for both READABLE and WRITABLE
+    - `send_to` / `recv_from` resolve once a single datagram is sent or received
+    - a datagram larger than the buffer passed to `recv_from` is truncated, like with std::net::UdpSocket
+    - dropping the socket deregisters it
+ */
+pub struct AsyncUdpSocket {
+    socket: mio::net::UdpSocket,
+    registration: Registration,
+}
+
+impl AsyncUdpSocket {
+    // binds to the first address `addr` resolves to; port 0 picks a free one
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<AsyncUdpSocket> {
+        let mut socket = mio::net::UdpSocket::bind(first_addr(addr)?)?;
+        let registration = Registration::new(&mut socket, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(AsyncUdpSocket { socket, registration })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn poll_send_to(&mut self, waker: &Waker, buf: &[u8], target: SocketAddr) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.socket.send_to(buf, target))
+    }
+
+    pub fn poll_recv_from(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<(usize, SocketAddr)>> {
+        self.registration.poll_io(waker, || self.socket.recv_from(buf))
+    }
+
+    // resolves to the number of bytes sent
+    pub fn send_to<'a>(&'a mut self, buf: &'a [u8], target: SocketAddr) -> SendTo<'a> {
+        SendTo { socket: self, buf, target }
+    }
+
+    // resolves to the size of the datagram and the address it came from
+    pub fn recv_from<'a>(&'a mut self, buf: &'a mut [u8]) -> RecvFrom<'a> {
+        RecvFrom { socket: self, buf }
+    }
+}
+
+impl Drop for AsyncUdpSocket {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.socket);
+    }
+}
+
+pub struct SendTo<'a> {
+    socket: &'a mut AsyncUdpSocket,
+    buf: &'a [u8],
+    target: SocketAddr,
+}
+
+impl Future for SendTo<'_> {
+    type Output = io::Result<usize>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.socket.poll_send_to(waker, self.buf, self.target)
+    }
+}
+
+pub struct RecvFrom<'a> {
+    socket: &'a mut AsyncUdpSocket,
+    buf: &'a mut [u8],
+}
+
+impl Future for RecvFrom<'_> {
+    type Output = io::Result<(usize, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.socket.poll_recv_from(waker, self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncUdpSocket;
+    use crate::{
+        runtime::{Runtime, Waker},
+        Future, PollState,
+    };
+    use std::{io, net::SocketAddr};
+
+    // Sends one datagram from `from` to `to`, then receives it on `to`
+    struct Datagram {
+        from: AsyncUdpSocket,
+        to: AsyncUdpSocket,
+        sent: bool,
+    }
+
+    impl Future for Datagram {
+        type Output = io::Result<(Vec<u8>, SocketAddr)>;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            if !self.sent {
+                let target = self.to.local_addr().unwrap();
+                match self.from.send_to(b"ping", target).poll(waker) {
+                    PollState::Ready(Ok(_)) => self.sent = true,
+                    PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                    PollState::NotReady => return PollState::NotReady,
+                }
+            }
+            let mut buf = [0u8; 64];
+            match self.to.recv_from(&mut buf).poll(waker) {
+                PollState::Ready(Ok((n, sender))) => PollState::Ready(Ok((buf[..n].to_vec(), sender))),
+                PollState::Ready(Err(e)) => PollState::Ready(Err(e)),
+                PollState::NotReady => PollState::NotReady,
+            }
+        }
+    }
+
+    #[test]
+    fn udp_loopback() {
+        let mut rt = Runtime::with_workers(1);
+        let (from, to) = {
+            let _context = rt.enter();
+            (AsyncUdpSocket::bind("127.0.0.1:0").unwrap(), AsyncUdpSocket::bind("127.0.0.1:0").unwrap())
+        };
+        assert_eq!(rt.reactor().live_registrations(), 2);
+
+        let sender = from.local_addr().unwrap();
+        let (received, from_addr) = rt.block_on(Datagram { from, to, sent: false }).unwrap();
+        assert_eq!(received, b"ping");
+        assert_eq!(from_addr, sender);
+        assert_eq!(rt.reactor().live_registrations(), 0);       // both sockets were dropped with the task
+    }
+}
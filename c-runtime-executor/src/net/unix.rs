@@ -0,0 +1,142 @@
+use crate::{
+    future::PollState,
+    io::{AsyncRead, AsyncWrite},
+    runtime::{Registration, Waker},
+    Future,
+};
+use mio::{
+    net::{SocketAddr, UnixListener, UnixStream},
+    Interest,
+};
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown,
+    path::Path,
+};
+
+/*
+AsyncUnixStream:
+    - a mio UnixStream registered with the Reactor once, for both READABLE and WRITABLE
+    - same token/waker scheme as AsyncTcpStream; dropping the stream deregisters it
+    - a connect usually finishes right away; where it's still in progress, `poll_connected` waits for it
+      like it does for TCP
+ */
+pub struct AsyncUnixStream {
+    stream: UnixStream,
+    registration: Registration,
+}
+
+impl AsyncUnixStream {
+    // starts a non-blocking connect; wait for `poll_connected` before reading or writing.
+    // WouldBlock means the listener's backlog is full: nothing was started, try again later.
+    pub fn connect(path: impl AsRef<Path>) -> io::Result<AsyncUnixStream> {
+        AsyncUnixStream::from_mio(UnixStream::connect(path)?)
+    }
+
+    pub fn from_mio(mut stream: UnixStream) -> io::Result<AsyncUnixStream> {
+        let registration = Registration::new(&mut stream, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(AsyncUnixStream { stream, registration })
+    }
+
+    // Ready(Ok(())) once a connect started by `connect` has finished
+    pub fn poll_connected(&mut self, waker: &Waker) -> PollState<io::Result<()>> {
+        self.registration.poll_connected(waker, || self.stream.take_error(), || self.stream.peer_addr())
+    }
+
+    // connected pair, e.g. to talk to a child process or between two tasks
+    pub fn pair() -> io::Result<(AsyncUnixStream, AsyncUnixStream)> {
+        let (a, b) = UnixStream::pair()?;
+        Ok((AsyncUnixStream::from_mio(a)?, AsyncUnixStream::from_mio(b)?))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.local_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    // reads without storing a Waker; WouldBlock when there's nothing to read
+    pub fn try_read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+
+    // stops waking the last task that waited on this stream, e.g. when it's put aside for later reuse
+    pub fn clear_waker(&self) {
+        self.registration.clear_waker();
+    }
+}
+
+impl AsyncRead for AsyncUnixStream {
+    fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.stream.read(buf))
+    }
+}
+
+impl AsyncWrite for AsyncUnixStream {
+    fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.stream.write(buf))
+    }
+}
+
+impl Drop for AsyncUnixStream {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.stream);
+    }
+}
+
+/*
+AsyncUnixListener:
+    - listening Unix socket registered with the Reactor for READABLE
+    - the socket file isn't removed on drop, like with std::os::unix::net::UnixListener
+ */
+pub struct AsyncUnixListener {
+    listener: UnixListener,
+    registration: Registration,
+}
+
+impl AsyncUnixListener {
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<AsyncUnixListener> {
+        let mut listener = UnixListener::bind(path)?;
+        let registration = Registration::new(&mut listener, Interest::READABLE)?;
+        Ok(AsyncUnixListener { listener, registration })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn poll_accept(&mut self, waker: &Waker) -> PollState<io::Result<(AsyncUnixStream, SocketAddr)>> {
+        self.registration.poll_io(waker, || {
+            let (stream, addr) = self.listener.accept()?;
+            Ok((AsyncUnixStream::from_mio(stream)?, addr))
+        })
+    }
+
+    pub fn accept(&mut self) -> UnixAccept<'_> {
+        UnixAccept { listener: self }
+    }
+}
+
+impl Drop for AsyncUnixListener {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.listener);
+    }
+}
+
+pub struct UnixAccept<'a> {
+    listener: &'a mut AsyncUnixListener,
+}
+
+impl Future for UnixAccept<'_> {
+    type Output = io::Result<(AsyncUnixStream, SocketAddr)>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        self.listener.poll_accept(waker)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{
+    future::PollState,
+    io::{AsyncRead, AsyncWrite},
+    runtime::{Registration, Waker},
+};
+use mio::{unix::pipe, Interest};
+use std::io::{self, Read, Write};
+
+/*
+Unix pipes:
+    - `pipe()` creates a connected pair; bytes written to the Sender come out of the Receiver
+    - both ends register with the Reactor like sockets do, the Sender for WRITABLE, the Receiver for READABLE
+    - `from_mio` wraps a mio pipe end, e.g. a child's stdio: `Receiver::from_mio(child.stdout.take().unwrap().into())`
+    - the Receiver reads EOF once every Sender is dropped
+ */
+pub fn pipe() -> io::Result<(Sender, Receiver)> {
+    let (sender, receiver) = pipe::new()?;
+    Ok((Sender::from_mio(sender)?, Receiver::from_mio(receiver)?))
+}
+
+pub struct Sender {
+    pipe: pipe::Sender,
+    registration: Registration,
+}
+
+impl Sender {
+    // puts the pipe into non-blocking mode, which a pipe converted from std isn't in yet
+    pub fn from_mio(mut pipe: pipe::Sender) -> io::Result<Sender> {
+        pipe.set_nonblocking(true)?;
+        let registration = Registration::new(&mut pipe, Interest::WRITABLE)?;
+        Ok(Sender { pipe, registration })
+    }
+}
+
+impl AsyncWrite for Sender {
+    fn poll_write(&mut self, waker: &Waker, buf: &[u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.pipe.write(buf))
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.pipe);
+    }
+}
+
+pub struct Receiver {
+    pipe: pipe::Receiver,
+    registration: Registration,
+}
+
+impl Receiver {
+    pub fn from_mio(mut pipe: pipe::Receiver) -> io::Result<Receiver> {
+        pipe.set_nonblocking(true)?;
+        let registration = Registration::new(&mut pipe, Interest::READABLE)?;
+        Ok(Receiver { pipe, registration })
+    }
+}
+
+impl AsyncRead for Receiver {
+    fn poll_read(&mut self, waker: &Waker, buf: &mut [u8]) -> PollState<io::Result<usize>> {
+        self.registration.poll_io(waker, || self.pipe.read(buf))
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.registration.deregister(&mut self.pipe);
+    }
+}
@@ -1,10 +1,140 @@
-pub use executor::{spawn, Executor, Waker};
-pub use reactor::reactor;
+pub use executor::{spawn, AbortHandle, Executor, JoinError, JoinHandle, Waker};
+pub use reactor::{shutdown_error, Reactor, Registration};
+pub use slab::{AtomicWaker, WakerSlab};
 
 mod executor;
 mod reactor;
+mod slab;
 
-pub fn init() -> Executor {
-    reactor::start();
-    Executor::new()
-}
\ No newline at end of file
+use crate::{http::Pool, Future};
+use std::{cell::RefCell, sync::Arc, thread, time::Duration};
+
+/*
+Context:
+    - what code running on a Runtime needs to reach: its Reactor and its HTTP connection pool
+    - set in a thread-local on every thread polling the Runtime's tasks while `block_on` runs,
+      so independent Runtimes (e.g. one per test) never share sources or pooled connections
+ */
+#[derive(Clone)]
+pub(crate) struct Context {
+    pub(crate) reactor: Arc<Reactor>,
+    pub(crate) pool: Arc<Pool>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+pub(crate) fn context() -> Context {
+    try_context().expect("Called outside an runtime context")
+}
+
+pub(crate) fn try_context() -> Option<Context> {
+    CONTEXT.with(|c| c.borrow().clone())
+}
+
+// makes `context` the current one until the guard is dropped; the previous one is restored then
+pub(crate) fn enter(context: Context) -> EnterGuard {
+    let previous = CONTEXT.with(|c| c.borrow_mut().replace(context));
+    EnterGuard { previous }
+}
+
+// returned by `Runtime::enter`
+pub struct EnterGuard {
+    previous: Option<Context>,
+}
+
+impl Drop for EnterGuard {
+    fn drop(&mut self) {
+        CONTEXT.with(|c| *c.borrow_mut() = self.previous.take());
+    }
+}
+
+// Reactor of the Runtime the current thread is running on; panics outside of `Runtime::block_on`
+pub fn reactor() -> Arc<Reactor> {
+    context().reactor
+}
+
+/*
+Runtime:
+    - owns an Executor, a Reactor with its event-loop thread, and the HTTP connection pool
+    - any number of Runtimes can exist side by side; each one's tasks only see its own Reactor
+    - dropping it (or calling `shutdown`) stops the Reactor and joins its thread, instead of leaving it
+      running until the process exits
+ */
+pub struct Runtime {
+    executor: Executor,
+    context: Context,
+    reactor_thread: Option<thread::JoinHandle<()>>,     // None once shut down
+}
+
+impl Runtime {
+    // starts the Reactor; one executor worker per available core
+    pub fn new() -> Self {
+        Self::with_executor(Executor::new())
+    }
+
+    pub fn with_workers(workers: usize) -> Self {
+        Self::with_executor(Executor::with_workers(workers))
+    }
+
+    // replaces the default HTTP pool limits, e.g. `Runtime::new().with_pool_limits(4, Duration::from_secs(5))`:
+    // at most `max_per_host` open connections per host, idle ones are closed after `idle_timeout`
+    pub fn with_pool_limits(mut self, max_per_host: usize, idle_timeout: Duration) -> Self {
+        self.context.pool = Arc::new(Pool::new(max_per_host, idle_timeout));
+        self
+    }
+
+    fn with_executor(executor: Executor) -> Self {
+        let (reactor, reactor_thread) = reactor::start();
+        let context = Context { reactor, pool: Arc::new(Pool::default()) };
+        Self { executor, context, reactor_thread: Some(reactor_thread) }
+    }
+
+    pub fn reactor(&self) -> &Arc<Reactor> {
+        &self.context.reactor
+    }
+
+    // makes this Runtime the current one outside of `block_on` until the guard is dropped,
+    // e.g. to bind a listener before handing it to a task
+    pub fn enter(&self) -> EnterGuard {
+        enter(self.context.clone())
+    }
+
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let _context = enter(self.context.clone());     // the executor hands it on to its worker threads
+        self.executor.block_on(future)
+    }
+
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        if let Some(handle) = self.reactor_thread.take() {
+            self.context.reactor.shutdown();
+            self.context.pool.shutdown();       // requests waiting for a pooled connection fail as well
+            handle.join().unwrap();
+        }
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub fn init() -> Runtime {
+    Runtime::new()
+}
@@ -1,106 +1,440 @@
 use crate::future::{Future, PollState};
 use std::{
-    cell::{Cell, RefCell},
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    any::Any,
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     thread::{self, Thread},
 };
 
-// type alias
-type Task = Box<dyn Future<Output = String>>;
-// macro defined to create a static variable unique to the thread; cannot access another thread's CURRENT_EXEC
+// type alias; every top-level future is type-erased to a future without output so tasks of
+// different Output types can live in the same `tasks` collection.
+// Send: a task may be polled by any worker thread
+type Task = Box<dyn Future<Output = ()> + Send>;
+// macro defined to create a static variable unique to the thread;
+// set on every worker thread while `block_on` runs so `spawn` and `Waker::wake` can find the scheduler
 thread_local! {
-    static CURRENT_EXEC: ExecutorCore = ExecutorCore::default();    // holds the current executor running on this thread
+    static CURRENT_WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
 }
 
 #[derive(Clone)]
 pub struct Waker {
-    thread: Thread,     // handle to the Thread object for parking and unparking
-    id: usize,          // Task id
-    ready_queue: Arc<Mutex<Vec<usize>>>,    // reference shared between threads, 
-                                            // Task IDs in the ready queue,
-                                            // shared with the executor to pus htask onto queue when ready.
+    id: usize,              // Task id
+    shared: Arc<Shared>,    // scheduler the task belongs to; shared between all worker threads
 }
 
 impl Waker {
     pub fn wake(&self) {
-        self.ready_queue
-            .lock()                         // take a lock on Mutex protecting the ReadyQueue
-            .map(|mut q| q.push(self.id))   // push the Task id onto the ready queue
-            .unwrap();
-        self.thread.unpark();               // Wake up the executor thread
+        self.shared.schedule(self.id);      // push the Task id onto a ready queue and unpark an idle worker
+    }
+
+    // both Wakers wake the same task
+    pub fn will_wake(&self, other: &Waker) -> bool {
+        self.id == other.id && Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+// Wakers of an executor that never runs, for tests; what they woke is read back with `woken`
+#[cfg(test)]
+impl Waker {
+    pub(crate) fn detached(ids: usize) -> Vec<Waker> {
+        let shared = Arc::new(Shared::new(0));
+        (0..ids).map(|id| Waker { id, shared: shared.clone() }).collect()
+    }
+
+    // Task ids woken through this executor since the last call, in order
+    pub(crate) fn woken(&self) -> Vec<usize> {
+        self.shared.injector.lock().unwrap().drain(..).collect()
     }
 }
 
 /*
-Our Executor implementation is NOT fully multithreaded because:
-    - Tasks / Futures cannot be sent from one thread to another
-    - different Executor instances are not aware of each other
-        - no work-stealing
-        - no global task queue
-    - To make it multithreaded, you need to:
-        - add constraints
-            - require everything to be Send + Sync
+Our Executor is a work-stealing, multithreaded scheduler:
+    - one `block_on` starts a fixed number of worker threads which all poll tasks of the same executor
+    - every worker owns a local queue; tasks spawned or woken on a worker thread are pushed there
+    - wakeups coming from outside the executor (e.g. the Reactor thread) go to the global injection queue
+    - a worker without work takes from the injection queue, then steals half of another worker's queue,
+      and only parks when there is nothing left to take
+    - tasks move between threads, so every future (and its output) has to be Send
 */
 
-#[derive(Default)]                          // Default trait: No special initial state needed
-struct ExecutorCore {
-    tasks: RefCell<HashMap<usize, Task>>,   /* hold all the Top-Level Futures in the executor on this thread
-                                            Internal Mutability achieved by RefCell, single thread, no synchronization needed
-                                            */
-    ready_queue: Arc<Mutex<Vec<usize>>>,    // Arc reference is shared to all Waker instances; can also be sent to a different thread
-    next_id: Cell<usize>,                   // Unique ID for each top-level future
+// A task is removed from its slot while it is being polled; a wakeup arriving during that poll is
+// remembered in `notified` so the task gets rescheduled once it is put back
+struct TaskSlot {
+    future: Option<Task>,
+    notified: bool,
 }
 
-pub fn spawn<F>(future: F) 
-where
-    F: Future<Output = String> + 'static    // 'static: lifetime of the Future must last until the ned of the program;
-                                            //      - have to give ownership over the things passed in;
-                                            //      - references NEED 'static lifetimes
-{
-    CURRENT_EXEC.with(|e| {
-        let id =  e.next_id.get();
-        e.tasks.borrow_mut().insert(id, Box::new(future));      // store in HashMap
-        e.ready_queue.lock().map(|mut q| q.push(id)).unwrap();  // add to ready_queue to poll it at least once
-        e.next_id.set(id + 1);
-    });
+struct Shared {
+    tasks: Mutex<HashMap<usize, TaskSlot>>,     // hold all the Top-Level Futures in the executor
+    injector: Mutex<VecDeque<usize>>,           // global injection queue
+    locals: Vec<Mutex<VecDeque<usize>>>,        // one local ready queue per worker
+    idle: Mutex<Vec<Thread>>,                   // parked workers, waiting to be unparked by `schedule`
+    next_id: AtomicUsize,                       // Unique ID for each top-level future
+    panic: Mutex<Option<Box<dyn Any + Send>>>,  // the first panic of a task; `block_on` resumes it
 }
 
-pub struct Executor;
+// Identifies the worker running on the current thread
+#[derive(Clone)]
+struct Worker {
+    shared: Arc<Shared>,
+    index: usize,           // index into `Shared::locals`
+}
 
-impl Executor {
-    /*
-    new(): creates new Executor instance
-    pop_ready: takes a lock on ready_queue and pop off an ID ready, removing from the collection
-    get_future: takes ID of a top-level future as an argument then removes the future from the tasks.
-                if the future returns NotReady the future should be added back to the collection.
-     */
-    pub fn new() -> Self {
-        Self {}
+impl Shared {
+    fn new(workers: usize) -> Self {
+        Self {
+            tasks: Mutex::new(HashMap::new()),
+            injector: Mutex::new(VecDeque::new()),
+            locals: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            idle: Mutex::new(vec![]),
+            next_id: AtomicUsize::new(0),
+            panic: Mutex::new(None),
+        }
+    }
+
+    fn spawn_task(self: &Arc<Self>, task: Task) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.tasks.lock().unwrap().insert(id, TaskSlot { future: Some(task), notified: false });   // store in HashMap
+        self.schedule(id);          // add to a ready queue to poll it at least once
+        id
     }
 
-    fn pop_ready(&self) -> Option<usize> {
-        CURRENT_EXEC.with(|q| q.ready_queue.lock().map(|mut q| q.pop()).unwrap())
+    // pushes to the local queue when called from one of our workers, to the injection queue otherwise
+    fn schedule(self: &Arc<Self>, id: usize) {
+        let local = CURRENT_WORKER.with(|w| {
+            w.borrow()
+                .as_ref()
+                .filter(|w| Arc::ptr_eq(&w.shared, self))
+                .map(|w| w.index)
+        });
+        match local {
+            Some(index) => self.locals[index].lock().unwrap().push_back(id),
+            None => self.injector.lock().unwrap().push_back(id),
+        }
+        // an idle worker takes the task from the injection queue or steals it from the local queue
+        if let Some(thread) = self.idle.lock().unwrap().pop() {
+            thread.unpark();
+        }
     }
 
-    fn get_future(&self, id: usize) -> Option<Task> {
-        CURRENT_EXEC.with(|q| q.tasks.borrow_mut().remove(&id))     // takes ownership and returns it
+    // local queue first, then the injection queue, then steal from the other workers
+    fn find_task(&self, index: usize) -> Option<usize> {
+        if let Some(id) = self.locals[index].lock().unwrap().pop_front() {
+            return Some(id);
+        }
+        if let Some(id) = self.injector.lock().unwrap().pop_front() {
+            return Some(id);
+        }
+        self.steal(index)
     }
 
-    fn get_waker(&self, id: usize) -> Waker {
-        Waker {
-            id,
-            thread: thread::current(),
-            ready_queue: CURRENT_EXEC.with(|q| q.ready_queue.clone()),
+    // takes the back half of the first non-empty queue of another worker
+    fn steal(&self, index: usize) -> Option<usize> {
+        let workers = self.locals.len();
+        for offset in 1..workers {
+            let victim = (index + offset) % workers;
+            // never hold two local queues at once; two workers stealing from each other would deadlock
+            let mut stolen = {
+                let mut queue = self.locals[victim].lock().unwrap();
+                let keep = queue.len() / 2;
+                queue.split_off(keep)
+            };
+            if let Some(id) = stolen.pop_front() {
+                self.locals[index].lock().unwrap().extend(stolen);
+                return Some(id);
+            }
         }
+        None
     }
 
-    fn insert_task(&self, id: usize, task: Task) {
-        CURRENT_EXEC.with(|q| q.tasks.borrow_mut().insert(id, task));
+    fn has_ready(&self) -> bool {
+        !self.injector.lock().unwrap().is_empty()
+            || self.locals.iter().any(|q| !q.lock().unwrap().is_empty())
     }
 
     fn task_count(&self) -> usize {
-        CURRENT_EXEC.with(|q| q.tasks.borrow().len())
+        self.tasks.lock().unwrap().len()
+    }
+
+    // unparks every worker so they can observe that all tasks are finished
+    fn unpark_all(&self) {
+        self.idle.lock().unwrap().drain(..).for_each(|t| t.unpark());
+    }
+
+    // A task panicked: like the single-threaded executor did, the panic ends `block_on`. Every task is
+    // dropped so the workers run out of tasks and finish; `block_on` then resumes the first panic.
+    fn fail(&self, payload: Box<dyn Any + Send>) {
+        self.panic.lock().unwrap().get_or_insert(payload);
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        drop(tasks);                // outside the lock; dropped futures may deregister sources or wake others
+        self.unpark_all();
+    }
+}
+
+impl Worker {
+    fn run(&self) {
+        let name = thread::current().name().unwrap_or_default().to_string();
+        loop {
+            // while loop runs as long as there are tasks to take from any ready queue
+            while let Some(id) = self.shared.find_task(self.index) {
+                self.poll_task(id);
+            }
+
+            let task_count = self.shared.task_count();
+            if task_count == 0 {
+                println!("{name}: All tasks are finished");
+                self.shared.unpark_all();
+                break;              // Done with the async program and exit the main `loop`
+            }
+
+            // register as idle before checking the queues again; a task scheduled after this check
+            // will find us in `idle` and unpark us (an early unpark makes the next `park` return immediately)
+            self.shared.idle.lock().unwrap().push(thread::current());
+            if self.shared.has_ready() || self.shared.task_count() == 0 {
+                self.remove_idle();
+                continue;
+            }
+            println!("{name}: {task_count} pending tasks. Sleep until notified.");
+            thread::park();         // yields control back to the OS scheduler and the worker is put to sleep
+            self.remove_idle();     // unparks can be spurious, don't stay on the idle list
+        }
+    }
+
+    fn remove_idle(&self) {
+        let id = thread::current().id();
+        self.shared.idle.lock().unwrap().retain(|t| t.id() != id);
+    }
+
+    fn poll_task(&self, id: usize) {
+        let mut future = {
+            let mut tasks = self.shared.tasks.lock().unwrap();
+            match tasks.get_mut(&id) {
+                // guard against false wakeups; mio doesn't guarantee false wakeups won't happen
+                None => return,
+                Some(slot) => match slot.future.take() {
+                    Some(future) => future,
+                    // another worker is polling the task right now; it will reschedule the task
+                    None => {
+                        slot.notified = true;
+                        return;
+                    }
+                },
+            }
+        };
+
+        let waker = Waker { id, shared: self.shared.clone() };
+        // a panicking task must not take its worker down with it; the others would wait for it forever
+        match panic::catch_unwind(AssertUnwindSafe(|| future.poll(&waker))) {
+            Err(payload) => {
+                drop(future);
+                self.shared.tasks.lock().unwrap().remove(&id);
+                self.shared.fail(payload);
+            }
+            Ok(PollState::NotReady) => {
+                // back to tasks collection, will be woken up by Waker::wake
+                let notified = {
+                    let mut tasks = self.shared.tasks.lock().unwrap();
+                    // gone if another task panicked meanwhile; then the future is just dropped
+                    let Some(slot) = tasks.get_mut(&id) else { return };
+                    slot.future = Some(future);
+                    std::mem::take(&mut slot.notified)
+                };
+                if notified {
+                    self.shared.schedule(id);
+                }
+            }
+            Ok(PollState::Ready(_)) => {
+                // Future object will be dropped since we have the ownership;
+                // its output was already handed over to the JoinHandle
+                let mut tasks = self.shared.tasks.lock().unwrap();
+                tasks.remove(&id);
+                if tasks.is_empty() {
+                    drop(tasks);
+                    self.shared.unpark_all();
+                }
+            }
+        }
+    }
+}
+
+/*
+JoinState:
+    - shared between a spawned task and its `JoinHandle`
+    - `output` is filled in by the executor once the task resolves
+    - `waker` belongs to whoever awaits the `JoinHandle`, so it can be woken when the output arrives
+ */
+struct JoinState<T> {
+    output: Option<T>,
+    waker: Option<Waker>,
+}
+
+/*
+TaskFuture:
+    - wraps a top-level future of any Output type so it can be stored as a `Task`
+    - when the inner future resolves, its value is handed to the `JoinState` and the awaiting task is woken;
+      the wrapper itself resolves to `()`
+    - once aborted, it resolves immediately without polling the inner future, so the executor drops it
+ */
+struct TaskFuture<F: Future> {
+    future: F,
+    join: Arc<Mutex<JoinState<F::Output>>>,
+    aborted: Arc<AtomicBool>,
+}
+
+impl<F: Future> Future for TaskFuture<F> {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if self.aborted.load(Ordering::Acquire) {
+            if let Some(waker) = self.join.lock().unwrap().waker.take() {
+                waker.wake();                   // the JoinHandle resolves to Err(JoinError::Aborted)
+            }
+            return PollState::Ready(());
+        }
+        match self.future.poll(waker) {
+            PollState::Ready(value) => {
+                let mut join = self.join.lock().unwrap();
+                join.output = Some(value);
+                if let Some(waker) = join.waker.take() {
+                    waker.wake();               // notify the task waiting on the JoinHandle
+                }
+                PollState::Ready(())
+            }
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum JoinError {
+    Aborted,        // the task was aborted before it finished
+}
+
+impl std::fmt::Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinError::Aborted => write!(f, "task was aborted"),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+/*
+JoinHandle:
+    - returned by `spawn`; a future resolving to the output of the spawned task
+    - resolves to Err(JoinError::Aborted) if the task was aborted before it finished
+    - dropping the handle detaches the task; it still runs to completion but its output is dropped
+ */
+pub struct JoinHandle<T> {
+    join: Arc<Mutex<JoinState<T>>>,
+    abort: AbortHandle,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn abort(&self) {
+        self.abort.abort();
+    }
+
+    pub fn abort_handle(&self) -> AbortHandle {
+        self.abort.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let mut join = self.join.lock().unwrap();
+        match join.output.take() {
+            Some(value) => PollState::Ready(Ok(value)),
+            None if self.abort.aborted.load(Ordering::Acquire) => PollState::Ready(Err(JoinError::Aborted)),
+            None => {
+                join.waker = Some(waker.clone());   // the most recent Waker should be stored
+                PollState::NotReady
+            }
+        }
+    }
+}
+
+/*
+AbortHandle:
+    - cancels a spawned task without needing its output type; can be used from any thread
+    - aborting marks the task and wakes it; the executor then removes it from `tasks` and drops the future,
+      which lets futures such as `HttpFuture` deregister their sources from the Reactor in `Drop`
+    - aborting a task that already finished has no effect
+ */
+#[derive(Clone)]
+pub struct AbortHandle {
+    aborted: Arc<AtomicBool>,
+    task: Waker,            // Waker of the spawned task, used to get it polled (and dropped) by the executor
+}
+
+impl AbortHandle {
+    pub fn abort(&self) {
+        if !self.aborted.swap(true, Ordering::AcqRel) {
+            self.task.wake();
+        }
+    }
+}
+
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,     // 'static: lifetime of the Future must last until the ned of the program;
+                                    //      - have to give ownership over the things passed in;
+                                    //      - references NEED 'static lifetimes
+                                    // Send: the task can be stolen by any worker thread
+    F::Output: Send + 'static,
+{
+    let shared = CURRENT_WORKER.with(|w| {
+        w.borrow()
+            .as_ref()
+            .map(|w| w.shared.clone())
+            .expect("`spawn` called outside of an executor")
+    });
+    spawn_on(&shared, future)
+}
+
+fn spawn_on<F>(shared: &Arc<Shared>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let join = Arc::new(Mutex::new(JoinState { output: None, waker: None }));
+    let aborted = Arc::new(AtomicBool::new(false));
+    let id = shared.spawn_task(Box::new(TaskFuture { future, join: join.clone(), aborted: aborted.clone() }));
+    let task = Waker { id, shared: shared.clone() };
+    JoinHandle { join, abort: AbortHandle { aborted, task } }
+}
+
+pub struct Executor {
+    workers: usize,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor {
+    /*
+    new(): creates new Executor instance with one worker per available core
+    with_workers(): creates new Executor instance with a fixed number of worker threads
+     */
+    pub fn new() -> Self {
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self::with_workers(workers)
+    }
+
+    pub fn with_workers(workers: usize) -> Self {
+        assert!(workers > 0, "Executor needs at least one worker");
+        Self { workers }
     }
 
     /*
@@ -108,38 +442,278 @@ impl Executor {
         - entry point to Executor
         - pass in one top-level future, which will spawn new top-level futures onto the Executor
         - each new future can then spawn new futures to the Executor too
-        - This implementation spawns tasks on the same thread, therefore removing the need for synchronization.
+        - the current thread becomes worker 0; `workers - 1` additional threads named `exec-N` are started
+          and all of them take part in polling the tasks
+        - returns the output of the top-level future once every task on the executor is finished
+        - if a task panics, the remaining tasks are dropped and the panic is resumed here
      */
-    pub fn block_on<F>(&mut self, future: F)
+    pub fn block_on<F>(&mut self, future: F) -> F::Output
     where
-        F: Future<Output = String> + 'static,
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
     {
-        spawn(future);  // spawn the future onto the current executor
-        // loop runs as long as the asynchronous program runs
-        loop {
-            // while loop runs as long as there are tasks in `ready_queue`
-            while let Some(id) = self.pop_ready() {
-                let mut future = match self.get_future(id) {
-                    Some(f) => f,
-                    // guard against false wakeups; mio doesn't guarantee false wakeups won't happen
-                    None => continue,
-                };
-                let waker = self.get_waker(id);
-                match future.poll(&waker) {
-                    PollState::NotReady => self.insert_task(id, future),    // back to tasks collection, will be waken up by Waker::wake
-                    PollState::Ready(_) => continue,        // Future object will be dropped since we have the ownership
+        let shared = Arc::new(Shared::new(self.workers));
+        let handle = spawn_on(&shared, future);  // spawn the future onto the executor
+        let context = super::try_context();      // the Runtime's Reactor, entered by `Runtime::block_on`
+
+        let threads: Vec<_> = (1..self.workers)
+            .map(|index| {
+                let worker = Worker { shared: shared.clone(), index };
+                let context = context.clone();
+                thread::Builder::new()
+                    .name(format!("exec-{index}"))
+                    .spawn(move || {
+                        let _context = context.map(super::enter);
+                        CURRENT_WORKER.with(|w| *w.borrow_mut() = Some(worker.clone()));
+                        worker.run();
+                        CURRENT_WORKER.with(|w| *w.borrow_mut() = None);
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        let worker = Worker { shared, index: 0 };
+        CURRENT_WORKER.with(|w| *w.borrow_mut() = Some(worker.clone()));
+        worker.run();
+        CURRENT_WORKER.with(|w| *w.borrow_mut() = None);
+        threads.into_iter().for_each(|t| t.join().unwrap());
+
+        if let Some(payload) = worker.shared.panic.lock().unwrap().take() {
+            panic::resume_unwind(payload);
+        }
+        let output = handle.join.lock().unwrap().output.take();
+        output.expect("Top-level future did not finish")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{spawn, Executor, JoinError, JoinHandle, Waker};
+    use crate::{
+        future::{Future, PollState},
+        http::Http,
+        runtime::Runtime,
+    };
+    use std::{
+        net::TcpListener,
+        panic::{self, AssertUnwindSafe},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc,
+        },
+        thread,
+    };
+
+    // Wakes itself and returns NotReady `yields` times, then finishes with the number of polls
+    // (or panics, if `panics` is set)
+    struct YieldNow {
+        yields: usize,
+        polls: usize,
+        panics: bool,
+    }
+
+    fn yield_now(yields: usize) -> YieldNow {
+        YieldNow { yields, polls: 0, panics: false }
+    }
+
+    impl Future for YieldNow {
+        type Output = usize;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            self.polls += 1;
+            if self.polls > self.yields {
+                assert!(!self.panics, "task failed");
+                return PollState::Ready(self.polls);
+            }
+            waker.wake();
+            PollState::NotReady
+        }
+    }
+
+    // Spawns the given futures from inside the executor on its first poll, then waits for all of them
+    struct SpawnAll<F: Future> {
+        futures: Vec<F>,
+        handles: Vec<JoinHandle<F::Output>>,
+        outputs: Vec<Option<Result<F::Output, JoinError>>>,
+    }
+
+    fn spawn_all<F: Future>(futures: Vec<F>) -> SpawnAll<F> {
+        SpawnAll { futures, handles: vec![], outputs: vec![] }
+    }
+
+    impl<F> Future for SpawnAll<F>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        type Output = Vec<Result<F::Output, JoinError>>;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            for future in self.futures.drain(..) {
+                self.handles.push(spawn(future));
+                self.outputs.push(None);
+            }
+            for (handle, output) in self.handles.iter_mut().zip(&mut self.outputs) {
+                if output.is_none() {
+                    if let PollState::Ready(result) = handle.poll(waker) {
+                        *output = Some(result);
+                    }
                 }
             }
-            let task_count = self.task_count();
-            let name = thread::current().name().unwrap_or_default().to_string();
+            if self.outputs.iter().any(Option::is_none) {
+                return PollState::NotReady;
+            }
+            PollState::Ready(self.outputs.drain(..).map(Option::unwrap).collect())
+        }
+    }
 
-            if task_count > 0 {
-                println!("{name}: {task_count} pending tasks. Sleep until notified.");
-                thread::park();     // yields control back to the OS scheduler and Executor is put to sleep
-            } else {
-                println!("{name}: All tasks are finished");
-                break;              // Done with the async program and exit the main `loop`
+    #[test]
+    fn panicking_task_ends_block_on() {
+        // the other tasks would never finish on their own; block_on must not wait for them
+        let mut tasks: Vec<_> = (0..8).map(|_| yield_now(usize::MAX)).collect();
+        tasks.push(YieldNow { panics: true, ..yield_now(10) });
+
+        let spawn_all = spawn_all(tasks);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| Executor::with_workers(3).block_on(spawn_all)));
+        let payload = result.expect_err("the panic of the task is resumed");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"task failed"));
+    }
+
+    #[test]
+    fn block_on_returns_the_output() {
+        assert_eq!(Executor::with_workers(2).block_on(yield_now(3)), 4);
+    }
+
+    #[test]
+    fn many_yielding_tasks_finish() {
+        let tasks = (0..200).map(|i| yield_now(i % 20)).collect();
+        let polls: Vec<_> = Executor::with_workers(4).block_on(spawn_all(tasks)).into_iter().map(Result::ok).collect();
+        let expected: Vec<_> = (0..200).map(|i| Some(i % 20 + 1)).collect();
+        assert_eq!(polls, expected);
+    }
+
+    // Wakes itself from another thread in the middle of its first poll, like the Reactor would
+    struct WokenWhilePolled {
+        polls: usize,
+    }
+
+    impl Future for WokenWhilePolled {
+        type Output = usize;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            self.polls += 1;
+            if self.polls > 1 {
+                return PollState::Ready(self.polls);
             }
-          }
+            let waker = waker.clone();
+            thread::spawn(move || waker.wake()).join().unwrap();
+            PollState::NotReady
+        }
+    }
+
+    #[test]
+    fn task_woken_while_polled_is_polled_again() {
+        // with one worker nothing else would wake the task; a lost wakeup would hang block_on
+        assert_eq!(Executor::with_workers(1).block_on(WokenWhilePolled { polls: 0 }), 2);
+    }
+
+    #[test]
+    fn join_handle_resolves_to_the_output_of_the_task() {
+        // the first task finishes before its handle is polled, the second one only afterwards
+        let outputs = Executor::with_workers(2).block_on(spawn_all(vec![yield_now(0), yield_now(5)]));
+        assert!(matches!(outputs[..], [Ok(1), Ok(6)]));
+    }
+
+    // Raises the flag once the inner future finished
+    struct SetWhenReady(YieldNow, Arc<AtomicBool>);
+
+    impl Future for SetWhenReady {
+        type Output = ();
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            match self.0.poll(waker) {
+                PollState::Ready(_) => {
+                    self.1.store(true, Ordering::Release);
+                    PollState::Ready(())
+                }
+                PollState::NotReady => PollState::NotReady,
+            }
+        }
+    }
+
+    // Spawns `YieldNow` and drops its JoinHandle right away; the task raises `done` when it finishes
+    struct Detach {
+        done: Arc<AtomicBool>,
+    }
+
+    impl Future for Detach {
+        type Output = ();
+
+        fn poll(&mut self, _waker: &Waker) -> PollState<Self::Output> {
+            drop(spawn(SetWhenReady(yield_now(5), self.done.clone())));
+            PollState::Ready(())
+        }
+    }
+
+    #[test]
+    fn dropped_join_handle_detaches_the_task() {
+        let done = Arc::new(AtomicBool::new(false));
+        Executor::with_workers(2).block_on(Detach { done: done.clone() });
+        assert!(done.load(Ordering::Acquire), "block_on waits for detached tasks too");
+    }
+
+    // Spawns the future and aborts it once `abort_when` returns true, then resolves to its JoinHandle's output
+    struct AbortWhen<F: Future, C> {
+        future: Option<F>,
+        handle: Option<JoinHandle<F::Output>>,
+        abort_when: C,
+    }
+
+    impl<F, C> Future for AbortWhen<F, C>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+        C: FnMut() -> bool,
+    {
+        type Output = Result<F::Output, JoinError>;
+
+        fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+            let future = self.future.take();
+            let handle = self.handle.get_or_insert_with(|| spawn(future.unwrap()));
+            if !(self.abort_when)() {
+                waker.wake();           // nothing else wakes us while we wait for the condition
+                return PollState::NotReady;
+            }
+            handle.abort();
+            handle.poll(waker)
+        }
+    }
+
+    #[test]
+    fn aborted_task_resolves_to_aborted() {
+        let abort = AbortWhen { future: Some(yield_now(usize::MAX)), handle: None, abort_when: || true };
+        let result = Executor::with_workers(2).block_on(abort);
+        assert!(matches!(result, Err(JoinError::Aborted)));
+    }
+
+    #[test]
+    fn aborting_a_request_deregisters_its_socket() {
+        // the server accepts the connection but never replies, so the request stays in flight
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (accepted, connection) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            accepted.send(()).unwrap();
+            stream
+        });
+
+        let mut rt = Runtime::with_workers(2);
+        let abort_when = move || connection.try_recv().is_ok();
+        let abort = AbortWhen { future: Some(Http::get(&url)), handle: None, abort_when };
+        let result = rt.block_on(abort);
+        assert!(matches!(result, Err(JoinError::Aborted)));
+        assert_eq!(rt.reactor().live_registrations(), 0);
+        drop(server.join().unwrap());
     }
 }
@@ -1,12 +1,16 @@
-use crate::runtime::Waker;
-use mio::{net::TcpStream, Events, Interest, Poll, Registry, Token};
+use super::slab::WakerSlab;
+use crate::{future::PollState, runtime::{reactor, Waker}};
+use mio::{event::Source, Events, Interest, Poll, Registry, Token};
 use std::{
-    collections::HashMap,
+    io::{self, ErrorKind},
+    cmp::Reverse,
+    collections::BinaryHeap,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Mutex, OnceLock,
+        atomic::{fence, AtomicBool, Ordering},
+        Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 
@@ -23,90 +27,362 @@ mio::Registry
 
 */
 
-// type alias for the Wakers collection
-type Wakers = Arc<Mutex<HashMap<usize, Waker>>>;
+// type alias for the Wakers collection; ids are WakerSlab keys, used as the Token of the source
+type Wakers = Arc<WakerSlab>;
+type Timers = Arc<Mutex<TimerHeap>>;
 
-// static variable, possible to access from idfferent threads
-// OnceLock: allows defining a static variable that we can write to once initialized in the start of Reactor
-static REACTOR: OnceLock<Reactor> = OnceLock::new();
-
-// public function allows accessing REACTOR
-pub fn reactor() -> &'static Reactor {
-    REACTOR.get().expect("Called outside an runtime context")
+/*
+TimerHeap:
+    - min-heap of (deadline, id); the nearest deadline is always on top
+    - a cancelled timer's entry stays behind, finding it would take a linear search, and is skipped when it
+      expires; once as many timers were deregistered as half the heap holds, the entries of released ids
+      are dropped in one pass, so repeatedly cancelled long sleeps don't grow the heap without bound
+ */
+#[derive(Default)]
+struct TimerHeap {
+    heap: BinaryHeap<Reverse<(Instant, usize)>>,
+    deregistered: usize,    // since the last prune; includes timers that had fired already
 }
 
+// Token reserved for the mio::Waker that interrupts `poll.poll`; ids handed out by `next_id` are never 0.
+// They're reused once deregistered, with a new generation so stale events for the old owner are dropped
+const WAKE_TOKEN: Token = Token(0);
+
 // Reactor Struct
+// owned by a `Runtime` and shared through `Arc`; code running on the Runtime reaches it with `runtime::reactor()`
 pub struct Reactor {
-    wakers: Wakers,         // HashMap of Waker objects
+    wakers: Wakers,         // allocates the ids and stores their Wakers, without a lock around all of them
     registry: Registry,     // Registry instance to interact with event queue in `mio`
-    next_id: AtomicUsize,   
+    timers: Timers,         // pending deadlines, shared with the event loop
+    poll_waker: mio::Waker, // interrupts a blocking `poll.poll` when an earlier deadline is registered, or on shutdown
+    shutdown: Arc<AtomicBool>,  // set once by `shutdown`; the event loop exits when it sees it
+}
+
+// error reported by sources once the Reactor is shut down, since no readiness event will ever arrive again
+pub fn shutdown_error() -> io::Error {
+    io::Error::other("reactor is shut down")
 }
 
 impl Reactor {
     // id is used to identify which event occurs
-    pub fn register(&self, stream: &mut TcpStream, interest: Interest, id: usize) {
-        self.registry.register(stream, Token(id), interest).unwrap();
+    // any mio event::Source works: TCP/UDP/Unix sockets, pipes, or a raw fd wrapped in `mio::unix::SourceFd`;
+    // fails if the OS can't poll the source (e.g. a regular file)
+    pub fn register<S: Source + ?Sized>(&self, source: &mut S, interest: Interest, id: usize) -> io::Result<()> {
+        if self.is_shutdown() {
+            return Err(shutdown_error());
+        }
+        self.registry.register(source, Token(id), interest)
     }
 
     // The most recent Waker should be stored; old Waker will be dropped
+    // After shutdown nothing would ever wake it, so it's woken right away; the source then reports `shutdown_error`.
+    // Storing happens before checking the flag, and the event loop sets the flag before waking everything,
+    // so a Waker stored concurrently is woken by one side or the other.
     pub fn set_waker(&self, waker: &Waker, id: usize) {
-        let _ = self.wakers
-                    .lock()
-                    .map(|mut w| w.insert(id, waker.clone()).is_none())
-                    .unwrap();
+        self.wakers.register(id, waker);
+        fence(Ordering::SeqCst);
+        if self.is_shutdown() {
+            self.wakers.wake(id);
+        }
     }
 
-    // Removes the Waker from wakers collection and deregsiter TcpStream from the Poll instance
-    pub fn deregister(&self, stream: &mut TcpStream, id: usize) {
-        self.wakers.lock().map(|mut w| w.remove(&id)).unwrap();
-        self.registry.deregister(stream).unwrap();              // Poll instance no longer monitors for readiness state change; 
+    // Removes the Waker but keeps the registration, e.g. for a connection kept alive in a pool
+    pub fn clear_waker(&self, id: usize) {
+        self.wakers.take(id);
+    }
+
+    // Deregsiter the source from the Poll instance and free its id for reuse
+    pub fn deregister<S: Source + ?Sized>(&self, source: &mut S, id: usize) {
+        self.registry.deregister(source).unwrap();              // Poll instance no longer monitors for readiness state change; 
                                                                 // interanal resources are cleared up
+        self.release(id);
+    }
+
+    /*
+    shutdown:
+        - stops the event loop: the flag is set, then `poll_waker` interrupts a blocking `poll.poll`
+        - the event loop wakes every pending Waker before it exits, so their futures are polled once more
+          and resolve with `shutdown_error` (a pending Delay resolves right away)
+        - `Runtime` calls it on drop and joins the event-loop thread
+     */
+    pub fn shutdown(&self) {
+        if !self.shutdown.swap(true, Ordering::SeqCst) {
+            self.poll_waker.wake().unwrap();
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
     }
 
+    // a free id; a deregistered one is handed out again with a new generation
     pub fn next_id(&self) -> usize {
-        self.next_id.fetch_add(1, Ordering::Relaxed)
+        self.wakers.allocate()
+    }
+
+    // drops the Waker and frees an id that was never registered, or is no longer
+    pub fn release(&self, id: usize) {
+        self.wakers.free(id);
+    }
+
+    // ids handed out and not released yet: registered sources plus pending timers
+    pub fn live_registrations(&self) -> usize {
+        self.wakers.live()
+    }
+
+    // The Waker stored for `id` is woken once `deadline` has passed; call `set_waker` first so a deadline
+    // that expires right away cannot fire before there is a Waker to wake
+    pub fn register_timer(&self, deadline: Instant, id: usize) {
+        let mut timers = self.timers.lock().unwrap();
+        let is_earliest = match timers.heap.peek() {
+            Some(Reverse((next, _))) => deadline < *next,
+            None => true,
+        };
+        timers.heap.push(Reverse((deadline, id)));
+        if is_earliest {
+            self.poll_waker.wake().unwrap();    // event loop is blocked with a later (or no) timeout; recompute it
+        }
+    }
+
+    // Frees the id; the heap entry is left behind and ignored when it expires, even if the id was reused
+    // by then, since the reused id carries a new generation
+    pub fn deregister_timer(&self, id: usize) {
+        self.release(id);
+        let mut timers = self.timers.lock().unwrap();
+        timers.deregistered += 1;
+        if timers.deregistered > timers.heap.len() / 2 {
+            timers.heap.retain(|Reverse((_, id))| self.wakers.contains(*id));
+            timers.deregistered = 0;
+        }
+    }
+}
+
+// time left until the nearest deadline; None if there are no timers
+fn next_timeout(timers: &Timers) -> Option<Duration> {
+    timers
+        .lock()
+        .unwrap()
+        .heap
+        .peek()
+        .map(|Reverse((deadline, _))| deadline.saturating_duration_since(Instant::now()))
+}
+
+// pops every expired deadline and wakes the associated waker
+fn fire_timers(timers: &Timers, wakers: &Wakers) {
+    let now = Instant::now();
+    let mut timers = timers.lock().unwrap();
+    while let Some(Reverse((deadline, id))) = timers.heap.peek().copied() {
+        if deadline > now {
+            break;
+        }
+        timers.heap.pop();
+        wakers.wake(id);                // no Waker if the timer was cancelled
     }
 }
 
 // logic for event loop that waits and reacts to new events
-fn event_loop(mut poll: Poll, wakers: Wakers) {
+fn event_loop(mut poll: Poll, wakers: Wakers, timers: Timers, shutdown: Arc<AtomicBool>) {
     // 
     let mut events = Events::with_capacity(100);
 
-    // loop never ends; this could be fixed but not necessary for this runtime
-    loop {
-        poll.poll(&mut events, None).unwrap();          // timeout None: never time out and block until it receives an event notification
+    // runs until `Reactor::shutdown` sets the flag and interrupts `poll.poll` through WAKE_TOKEN
+    while !shutdown.load(Ordering::SeqCst) {
+        // block until an event notification arrives or the nearest deadline passes;
+        // timeout None: no timers, never time out
+        let timeout = next_timeout(&timers);
+        match poll.poll(&mut events, timeout) {
+            Ok(()) => (),
+            // a signal arrived while blocked (EINTR); no events are lost, so wait again with a fresh timeout
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => panic!("polling for events failed: {e}"),
+        }
         // loop through every events received by poll.poll()
         for e in events.iter() {
-            let Token(id) = e.token();
-            let wakers = wakers.lock().unwrap();        // get the id-associated waker
-            if let Some(waker) = wakers.get(&id) {      // wake only if the waker exists; non-existent if we removed it from the collection already
-                waker.wake();
+            if e.token() == WAKE_TOKEN {
+                continue;                               // recompute the timeout, or check the shutdown flag
             }
+            let Token(id) = e.token();
+            wakers.wake(id);                        // wake only if a Waker is stored; none if it was cleared already
         }
+        fire_timers(&timers, &wakers);
     }
+
+    // nothing will be woken by an event again; let every waiting future see the shutdown
+    fence(Ordering::SeqCst);
+    wakers.wake_all();
 }
 
-// initializes and starts the runtime
-pub fn start() {
-    use thread::spawn;          // import spawn form std::thread
-    let wakers = Arc::new(Mutex::new(HashMap::new()));
+// initializes and starts a Reactor; the event loop runs until `Reactor::shutdown`, then the thread finishes
+pub fn start() -> (Arc<Reactor>, JoinHandle<()>) {
+    let wakers = Arc::new(WakerSlab::new());
+    let timers = Arc::new(Mutex::new(TimerHeap::default()));
+    let shutdown = Arc::new(AtomicBool::new(false));
     let poll = Poll::new().unwrap();
     let registry = poll.registry().try_clone().unwrap();    // own Registry
-    let next_id = AtomicUsize::new(1);
-    let reactor = Reactor {
+    let poll_waker = mio::Waker::new(poll.registry(), WAKE_TOKEN).unwrap();
+    let reactor = Arc::new(Reactor {
         wakers: wakers.clone(),
         registry,
-        next_id,
+        timers: timers.clone(),
+        poll_waker,
+        shutdown: shutdown.clone(),
+    });
+    // spawn a new OS thread and start `event_loop`; the JoinHandle lets `Runtime` wait for it to finish
+    let handle = thread::Builder::new()
+        .name("reactor".to_string())
+        .spawn(move || event_loop(poll, wakers, timers, shutdown))
+        .unwrap();
+    (reactor, handle)
+}
+
+/*
+Registration:
+    - a source's id with the Reactor it was registered with; every reactor-backed type keeps one next to
+      its mio source, so it's deregistered from the right Reactor even when dropped outside the Runtime
+    - `poll_io` holds the WouldBlock / Interrupted handling all of them share
+ */
+pub struct Registration {
+    reactor: Arc<Reactor>,
+    id: usize,
+}
+
+impl Registration {
+    // registers `source` with the current Runtime's Reactor under a new id
+    pub fn new<S: Source + ?Sized>(source: &mut S, interest: Interest) -> io::Result<Registration> {
+        let reactor = reactor();
+        let id = reactor.next_id();
+        if let Err(e) = reactor.register(source, interest, id) {
+            reactor.release(id);
+            return Err(e);
+        }
+        Ok(Registration { reactor, id })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn reactor(&self) -> &Arc<Reactor> {
+        &self.reactor
+    }
+
+    pub fn set_waker(&self, waker: &Waker) {
+        self.reactor.set_waker(waker, self.id);
+    }
+
+    // stops waking the last task that waited on the source, e.g. when it's put aside for later reuse
+    pub fn clear_waker(&self) {
+        self.reactor.clear_waker(self.id);
+    }
+
+    // call from the source's Drop
+    pub fn deregister<S: Source + ?Sized>(&self, source: &mut S) {
+        self.reactor.deregister(source, self.id);
+    }
+
+    // retries `op` on Interrupted; on WouldBlock NotReady is returned, unless the Reactor is shut down
+    // and there's nothing to wait for anymore.
+    // The Waker is stored before `op` runs: events are edge-triggered, so one arriving between `op` hitting
+    // WouldBlock and the Waker being stored would be lost.
+    pub fn poll_io<T>(&self, waker: &Waker, mut op: impl FnMut() -> io::Result<T>) -> PollState<io::Result<T>> {
+        self.set_waker(waker);
+        loop {
+            match op() {
+                Err(e) if e.kind() == ErrorKind::WouldBlock && self.reactor.is_shutdown() => {
+                    break PollState::Ready(Err(shutdown_error()));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break PollState::NotReady,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => break PollState::Ready(result),
+            }
+        }
+    }
+
+    // Ready(Ok(())) once a non-blocking connect of the source has finished, for any socket type:
+    // `take_error` reports a failed connect, `peer_addr` fails with NotConnected while it's still in progress.
+    // The Waker is stored first, so the WRITABLE event of the finished connect can't slip past.
+    pub fn poll_connected<A>(
+        &self,
+        waker: &Waker,
+        take_error: impl FnOnce() -> io::Result<Option<io::Error>>,
+        peer_addr: impl FnOnce() -> io::Result<A>,
+    ) -> PollState<io::Result<()>> {
+        self.set_waker(waker);
+        match take_error() {
+            Ok(Some(e)) | Err(e) => return PollState::Ready(Err(e)),
+            Ok(None) => (),
+        }
+        match peer_addr() {
+            Ok(_) => PollState::Ready(Ok(())),
+            Err(e) if e.kind() == ErrorKind::NotConnected && self.reactor.is_shutdown() => {
+                PollState::Ready(Err(shutdown_error()))
+            }
+            Err(e) if e.kind() == ErrorKind::NotConnected => PollState::NotReady,
+            Err(e) => PollState::Ready(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        future::{timeout, Elapsed},
+        http::Http,
+        net::TcpListener,
+        runtime::{Runtime, Waker},
+        time::sleep,
+        Future, PollState,
     };
-    REACTOR.set(reactor).ok().expect("Reactor already running");
-    // spawn a new OS thread and start `event_loop`
-    spawn(move || event_loop(poll, wakers));    // returns a JoinHandler, can be used to join the thread
-    /*
-    Example of using the JoinHandler:
-        ```
-        let handler = spawn(|| { ... });
-        handler.join().unwrap();                // join waits for the spawned thread to finish; need a logic in event_loop to finish and return
-        ```
-     */
-}
\ No newline at end of file
+    use std::{net, time::Duration};
+
+    #[test]
+    fn live_registrations_follow_sources_and_timers() {
+        let rt = Runtime::with_workers(1);
+        let _context = rt.enter();
+        let reactor = rt.reactor().clone();
+        assert_eq!(reactor.live_registrations(), 0);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let other = TcpListener::bind("127.0.0.1:0").unwrap();
+        assert_eq!(reactor.live_registrations(), 2);
+
+        let mut delay = sleep(Duration::from_secs(60));
+        let waker = Waker::detached(1).remove(0);
+        assert!(matches!(delay.poll(&waker), PollState::NotReady));
+        assert_eq!(reactor.live_registrations(), 3);
+
+        drop(listener);
+        drop(delay);
+        assert_eq!(reactor.live_registrations(), 1);
+        drop(other);
+        assert_eq!(reactor.live_registrations(), 0);
+    }
+
+    #[test]
+    fn cancelled_timers_are_pruned() {
+        let rt = Runtime::with_workers(1);
+        let _context = rt.enter();
+        let waker = Waker::detached(1).remove(0);
+
+        let mut kept = sleep(Duration::from_secs(60));
+        assert!(matches!(kept.poll(&waker), PollState::NotReady));
+        for _ in 0..1_000 {
+            let mut delay = sleep(Duration::from_secs(60));
+            assert!(matches!(delay.poll(&waker), PollState::NotReady));
+        }
+        assert!(rt.reactor().timers.lock().unwrap().heap.len() <= 3);
+        assert_eq!(rt.reactor().live_registrations(), 1);
+    }
+
+    #[test]
+    fn timed_out_request_releases_its_registrations() {
+        // accepted by the backlog but never answered
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let mut rt = Runtime::with_workers(1);
+        let result = rt.block_on(timeout(Duration::from_millis(50), Http::get(&url)));
+        assert!(matches!(result, Err(Elapsed)));
+        assert_eq!(rt.reactor().live_registrations(), 0);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::runtime::Waker;
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+/*
+AtomicWaker:
+    - one Waker slot that `register` and `wake` may use from different threads at the same time, without a lock
+    - `state` says who's touching the Waker right now:
+        WAITING: nobody; REGISTERING: `register` is storing a new Waker; WAKING: `wake` or `take` is using it
+    - `wake` leaves the Waker in place: readiness is edge-triggered, so an event arriving before the task
+      stored a new Waker must still reach it through the old one
+    - a `wake` that runs into a `register` only marks WAKING; `register` sees that when it's done storing
+      and wakes the new Waker itself, so a wakeup is never lost
+ */
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
+
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// the Waker is only accessed by whoever moved `state` away from WAITING
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self { state: AtomicUsize::new(WAITING), waker: UnsafeCell::new(None) }
+    }
+
+    // stores `waker`, replacing the previous one
+    pub fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(WAITING, REGISTERING, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => {
+                // SAFETY: REGISTERING keeps `take` away from the Waker until we're done
+                unsafe { *self.waker.get() = Some(waker.clone()) };
+                if self.state.compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                    // a `wake` came by while we were storing it: the state is REGISTERING | WAKING
+                    self.state.store(WAITING, Ordering::Release);
+                    waker.wake();
+                }
+            }
+            // being woken right now through the old Waker; make sure the new one is woken as well
+            Err(WAKING) => waker.wake(),
+            // another `register` for the same slot is running; one task per slot, so nothing to do
+            Err(_) => (),
+        }
+    }
+
+    // removes the Waker, e.g. when the slot is freed; None if there's none, or if a concurrent
+    // `register` or `wake` is using it
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                // SAFETY: WAKING keeps `register` away from the Waker until we're done
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            }
+            _ => None,
+        }
+    }
+
+    // a copy of the stored Waker, which stays in place; None while a concurrent `register` is storing a
+    // new one, which it then wakes itself
+    pub fn waker(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                // SAFETY: WAKING keeps `register` away from the Waker until we're done
+                let waker = unsafe { (*self.waker.get()).clone() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            }
+            _ => None,
+        }
+    }
+
+    // wakes the stored Waker and keeps it
+    pub fn wake(&self) {
+        if let Some(waker) = self.waker() {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// page `n` holds FIRST_PAGE << n slots, so MAX_PAGES pages hold more tokens than any Poll will ever see
+const FIRST_PAGE: usize = 32;
+const MAX_PAGES: usize = 24;
+
+// a key is `generation << INDEX_BITS | (index + 1)`; the low bits are never 0, so Token(0) stays free
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+// generations wrap around within the remaining bits (16 of them on 32-bit targets)
+const GENERATION_MASK: usize = usize::MAX >> INDEX_BITS;
+
+struct Slot {
+    generation: AtomicUsize,    // bumped every time the slot is freed, wrapping at GENERATION_MASK
+    waker: AtomicWaker,
+}
+
+/*
+WakerSlab:
+    - token allocator and waker storage in one; `allocate` hands out a key that's used as the mio Token
+    - freed slots are handed out again, instead of counting up forever; the key carries the slot's
+      generation, so an event or timer still in flight for the previous owner no longer matches and is
+      dropped instead of waking the new owner
+    - slots live in pages that are allocated once and never move, so `register` and `wake` only touch the
+      slot's AtomicWaker; just `allocate` and `free` take the free-list lock
+ */
+pub struct WakerSlab {
+    pages: [OnceLock<Box<[Slot]>>; MAX_PAGES],
+    free: Mutex<Vec<usize>>,    // freed indices, reused most recent first
+    len: AtomicUsize,           // indices ever handed out; everything below is backed by a page
+    live: AtomicUsize,          // keys allocated and not freed yet
+}
+
+impl WakerSlab {
+    pub fn new() -> Self {
+        Self {
+            pages: [const { OnceLock::new() }; MAX_PAGES],
+            free: Mutex::new(vec![]),
+            len: AtomicUsize::new(0),
+            live: AtomicUsize::new(0),
+        }
+    }
+
+    // key of an empty slot
+    pub fn allocate(&self) -> usize {
+        let index = self.free.lock().unwrap().pop().unwrap_or_else(|| {
+            let index = self.len.fetch_add(1, Ordering::Relaxed);
+            let (page, _) = locate(index);
+            assert!(page < MAX_PAGES && index < INDEX_MASK, "WakerSlab is full");
+            self.pages[page].get_or_init(|| {
+                (0..FIRST_PAGE << page)
+                    .map(|_| Slot { generation: AtomicUsize::new(0), waker: AtomicWaker::new() })
+                    .collect()
+            });
+            index
+        });
+        self.live.fetch_add(1, Ordering::Relaxed);
+        let generation = self.slot(index).generation.load(Ordering::Acquire);
+        (generation << INDEX_BITS) | (index + 1)
+    }
+
+    // drops the slot's Waker and makes it available to `allocate` again; a stale key is ignored
+    pub fn free(&self, key: usize) {
+        let (Some(index), Some(slot)) = (index_of(key), self.get(key)) else { return };
+        // only one of two racing `free`s for the same key gets to bump the generation and return the index
+        let generation = key >> INDEX_BITS;
+        let next = generation.wrapping_add(1) & GENERATION_MASK;
+        if slot.generation.compare_exchange(generation, next, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return;
+        }
+        slot.waker.take();
+        self.free.lock().unwrap().push(index);
+        self.live.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn register(&self, key: usize, waker: &Waker) {
+        if let Some(slot) = self.get(key) {
+            slot.waker.register(waker);
+        }
+    }
+
+    pub fn take(&self, key: usize) -> Option<Waker> {
+        self.get(key)?.waker.take()
+    }
+
+    pub fn waker(&self, key: usize) -> Option<Waker> {
+        self.get(key)?.waker.waker()
+    }
+
+    // a stale key (the slot was freed since) wakes nothing; a key freed while this runs may still see
+    // the new owner's Waker, and a spurious wakeup is harmless
+    pub fn wake(&self, key: usize) {
+        if let Some(slot) = self.get(key) {
+            slot.waker.wake();
+        }
+    }
+
+    // false once the key was freed
+    pub fn contains(&self, key: usize) -> bool {
+        self.get(key).is_some()
+    }
+
+    // wakes every stored Waker, e.g. on shutdown
+    pub fn wake_all(&self) {
+        let len = self.len.load(Ordering::Acquire);
+        // an index allocated concurrently may not have its page yet; its slot is empty anyway
+        (0..len).filter_map(|index| self.try_slot(index)).for_each(|slot| slot.waker.wake());
+    }
+
+    // number of keys in use, for diagnostics
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    // the slot `key` refers to, if it's still the generation the key was handed out for
+    fn get(&self, key: usize) -> Option<&Slot> {
+        let slot = self.try_slot(index_of(key)?)?;
+        (slot.generation.load(Ordering::Acquire) == key >> INDEX_BITS).then_some(slot)
+    }
+
+    fn slot(&self, index: usize) -> &Slot {
+        self.try_slot(index).expect("index was never allocated")
+    }
+
+    fn try_slot(&self, index: usize) -> Option<&Slot> {
+        let (page, offset) = locate(index);
+        self.pages.get(page)?.get().map(|page| &page[offset])
+    }
+}
+
+impl Default for WakerSlab {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// None for a key no slot was ever handed out for, e.g. Token(0)
+fn index_of(key: usize) -> Option<usize> {
+    (key & INDEX_MASK).checked_sub(1)
+}
+
+// (page, offset) of `index`; pages start at FIRST_PAGE * (2^n - 1)
+fn locate(index: usize) -> (usize, usize) {
+    let shifted = index + FIRST_PAGE;
+    let page = (usize::BITS - 1 - shifted.leading_zeros()) as usize - FIRST_PAGE.trailing_zeros() as usize;
+    (page, shifted - (FIRST_PAGE << page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn locate_pages() {
+        assert_eq!(locate(0), (0, 0));
+        assert_eq!(locate(FIRST_PAGE - 1), (0, FIRST_PAGE - 1));
+        assert_eq!(locate(FIRST_PAGE), (1, 0));
+        assert_eq!(locate(3 * FIRST_PAGE - 1), (1, 2 * FIRST_PAGE - 1));
+        assert_eq!(locate(3 * FIRST_PAGE), (2, 0));
+        assert_eq!(locate(7 * FIRST_PAGE), (3, 0));
+    }
+
+    #[test]
+    fn freed_index_is_reused_with_new_generation() {
+        let slab = WakerSlab::new();
+        let first = slab.allocate();
+        let second = slab.allocate();
+        assert_ne!(index_of(first), index_of(second));
+
+        slab.free(first);
+        let reused = slab.allocate();
+        assert_eq!(index_of(reused), index_of(first));
+        assert_ne!(reused, first);
+        assert_eq!(slab.allocate() & INDEX_MASK, 3);     // the free list is empty again
+    }
+
+    #[test]
+    fn slots_beyond_the_first_page() {
+        let slab = WakerSlab::new();
+        let wakers = Waker::detached(1);
+        let keys: Vec<usize> = (0..3 * FIRST_PAGE).map(|_| slab.allocate()).collect();
+        let last = *keys.last().unwrap();
+        slab.register(last, &wakers[0]);
+        slab.wake(last);
+        assert_eq!(wakers[0].woken(), [0]);
+    }
+
+    #[test]
+    fn stale_key_is_ignored() {
+        let slab = WakerSlab::new();
+        let wakers = Waker::detached(2);
+        let stale = slab.allocate();
+        slab.free(stale);
+        let key = slab.allocate();
+        slab.register(key, &wakers[1]);
+
+        slab.register(stale, &wakers[0]);
+        slab.wake(stale);
+        assert!(wakers[0].woken().is_empty());
+        assert!(slab.take(stale).is_none());
+
+        slab.free(stale);
+        assert_eq!(slab.live(), 1);
+        slab.wake(key);
+        assert_eq!(wakers[0].woken(), [1]);     // still the new owner's slot and Waker
+    }
+
+    #[test]
+    fn generation_wraps_around() {
+        let slab = WakerSlab::new();
+        let first = slab.allocate();
+        slab.free(first);
+        slab.slot(0).generation.store(GENERATION_MASK, Ordering::SeqCst);     // as if freed that often
+
+        let last = slab.allocate();
+        assert_eq!(last >> INDEX_BITS, GENERATION_MASK);
+        slab.free(last);
+        let wrapped = slab.allocate();
+        assert_eq!(wrapped, 1);             // generation 0 again, index 0
+        assert_eq!(slab.live(), 1);
+    }
+
+    #[test]
+    fn concurrent_free_returns_the_index_once() {
+        for _ in 0..1_000 {
+            let slab = Arc::new(WakerSlab::new());
+            let key = slab.allocate();
+            let racing = {
+                let slab = slab.clone();
+                thread::spawn(move || slab.free(key))
+            };
+            slab.free(key);
+            racing.join().unwrap();
+
+            assert_eq!(slab.live(), 0);
+            assert_eq!(*slab.free.lock().unwrap(), [0]);
+        }
+    }
+
+    #[test]
+    fn live_counts_allocated_keys() {
+        let slab = WakerSlab::new();
+        let keys: Vec<usize> = (0..5).map(|_| slab.allocate()).collect();
+        assert_eq!(slab.live(), 5);
+        keys[..3].iter().for_each(|&key| slab.free(key));
+        slab.free(keys[0]);
+        assert_eq!(slab.live(), 2);
+        slab.allocate();
+        assert_eq!(slab.live(), 3);
+    }
+
+    #[test]
+    fn wake_keeps_the_waker() {
+        let wakers = Waker::detached(1);
+        let slot = AtomicWaker::new();
+        slot.register(&wakers[0]);
+        slot.wake();
+        slot.wake();
+        assert_eq!(wakers[0].woken(), [0, 0]);
+        assert!(slot.take().is_some());
+        slot.wake();
+        assert!(wakers[0].woken().is_empty());
+    }
+
+    #[test]
+    fn register_during_wake_wakes_new_waker() {
+        let wakers = Waker::detached(2);
+        let slot = AtomicWaker::new();
+        slot.register(&wakers[0]);
+        slot.state.store(WAKING, Ordering::SeqCst);     // a `wake` is busy with wakers[0]
+        slot.register(&wakers[1]);
+        assert_eq!(wakers[0].woken(), [1]);
+    }
+
+    #[test]
+    fn wake_during_register_is_not_lost() {
+        for _ in 0..1_000 {
+            let wakers = Waker::detached(2);
+            let slot = Arc::new(AtomicWaker::new());
+            slot.register(&wakers[0]);
+
+            let registering = {
+                let (slot, waker) = (slot.clone(), wakers[1].clone());
+                thread::spawn(move || slot.register(&waker))
+            };
+            slot.wake();
+            registering.join().unwrap();
+
+            assert_eq!(slot.state.load(Ordering::SeqCst), WAITING);
+            // the wake reached the old Waker before `register`, or the new one after it
+            let woken = wakers[0].woken();
+            assert!(!woken.is_empty());
+            // whatever is stored now is the new Waker, unless `register` woke it instead of storing it
+            slot.wake();
+            let stored = wakers[0].woken();
+            assert!(stored == [1] || woken.contains(&1), "woken {woken:?}, stored {stored:?}");
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{future::PollState, runtime::{reactor, Reactor, Waker}, Future};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/*
+Delay:
+    - future that resolves once its deadline has passed
+    - registers the deadline with the Reactor on the first poll; the Reactor wakes the task when it expires
+    - dropping a pending Delay cancels the timer
+    - once the Reactor is shut down it resolves right away, since the timer would never fire
+ */
+pub struct Delay {
+    deadline: Instant,
+    timer: Option<(Arc<Reactor>, usize)>,   // Reactor and id; None until the timer is registered
+}
+
+// resolves after `duration` has elapsed
+pub fn sleep(duration: Duration) -> Delay {
+    sleep_until(Instant::now() + duration)
+}
+
+// resolves once `deadline` is reached
+pub fn sleep_until(deadline: Instant) -> Delay {
+    Delay { deadline, timer: None }
+}
+
+impl Delay {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let shut_down = self.timer.as_ref().is_some_and(|(reactor, _)| reactor.is_shutdown());
+        if self.is_elapsed() || shut_down {
+            if let Some((reactor, id)) = self.timer.take() {
+                reactor.deregister_timer(id);
+            }
+            return PollState::Ready(());
+        }
+
+        match &self.timer {
+            // the timer is already registered; keep the most recent Waker
+            Some((reactor, id)) => reactor.set_waker(waker, *id),
+            None => {
+                let reactor = reactor();
+                let id = reactor.next_id();
+                reactor.set_waker(waker, id);      // woken right away if the Reactor is shut down already
+                reactor.register_timer(self.deadline, id);
+                self.timer = Some((reactor, id));
+            }
+        }
+        PollState::NotReady
+    }
+}
+
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some((reactor, id)) = self.timer.take() {
+            reactor.deregister_timer(id);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+/*
+Offline loopback tests for the HTTP client:
+    - a blocking std server on 127.0.0.1:0 runs on a thread of the test process
+    - it answers the requests it reads with the canned responses, in order, and reports what it received
+ */
+use c_runtime_executor::{
+    future::{join_all, Stream},
+    http::{Http, HttpError, Request},
+    runtime::Runtime,
+};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+// a request as the server saw it: the connection it arrived on (numbered from 0), head and body
+type Received = (usize, String, Vec<u8>);
+
+fn serve(responses: &[&'static str]) -> (String, JoinHandle<Vec<Received>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mut responses = responses.to_vec();
+    responses.reverse();
+
+    let server = thread::spawn(move || {
+        let mut received = vec![];
+        for (conn, stream) in listener.incoming().enumerate() {
+            let mut stream = BufReader::new(stream.unwrap());
+            while !responses.is_empty() {
+                let Some((head, body)) = read_request(&mut stream) else { break };
+                received.push((conn, head, body));
+                stream.get_mut().write_all(responses.pop().unwrap().as_bytes()).unwrap();
+            }
+            if responses.is_empty() {
+                break;
+            }
+        }
+        received
+    });
+    (url, server)
+}
+
+// None once the client closed the connection
+fn read_request(stream: &mut BufReader<TcpStream>) -> Option<(String, Vec<u8>)> {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let length = head
+        .lines()
+        .find_map(|l| l.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).ok()?;
+    Some((head, body))
+}
+
+#[test]
+fn get_and_post() {
+    let (url, server) = serve(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nX-Test: yes\r\n\r\nhello",
+        "HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n",
+    ]);
+    let mut rt = Runtime::with_workers(1);
+
+    let response = rt.block_on(Http::get(&format!("{url}/greeting?lang=en"))).unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("x-test"), Some("yes"));
+    assert_eq!(response.text(), "hello");
+
+    let response = rt.block_on(Http::send(Request::post(&format!("{url}/items")).body("{\"id\":1}"))).unwrap();
+    assert_eq!(response.status(), 201);
+
+    let received = server.join().unwrap();
+    assert!(received[0].1.starts_with("GET /greeting?lang=en HTTP/1.1\r\n"));
+    assert!(received[0].1.contains(&format!("Host: {}\r\n", url.trim_start_matches("http://"))));
+    assert!(received[1].1.starts_with("POST /items HTTP/1.1\r\n"));
+    assert_eq!(received[1].2, b"{\"id\":1}");
+}
+
+#[test]
+fn keep_alive_connection_is_reused() {
+    let (url, server) = serve(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none",
+        "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+    ]);
+    let mut rt = Runtime::with_workers(1);
+    let client = Http::client(&url);
+
+    assert_eq!(rt.block_on(client.get("/1")).unwrap().text(), "one");
+    assert_eq!(rt.block_on(client.get("/2")).unwrap().text(), "two");
+
+    let connections: Vec<usize> = server.join().unwrap().iter().map(|(conn, ..)| *conn).collect();
+    assert_eq!(connections, [0, 0]);
+}
+
+#[test]
+fn pool_limit_makes_requests_share_a_connection() {
+    // `serve` only takes the next connection once the current one is done, so a second
+    // connection opened before that would never be answered
+    let (url, server) = serve(&[
+        "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none",
+        "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\ntwo",
+    ]);
+    let mut rt = Runtime::with_workers(1).with_pool_limits(1, Duration::from_secs(30));
+    let client = Http::client(&url);
+
+    rt.block_on(join_all(vec![client.get("/1"), client.get("/2")]));
+
+    let connections: Vec<usize> = server.join().unwrap().iter().map(|(conn, ..)| *conn).collect();
+    assert_eq!(connections, [0, 0]);
+}
+
+#[test]
+fn chunked_body_is_streamed() {
+    let (url, server) = serve(&["HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nstream\r\n4\r\ning!\r\n0\r\n\r\n"]);
+    let mut rt = Runtime::with_workers(1);
+
+    let chunks: Vec<_> = rt.block_on(Http::get_stream(&url).collect());
+    let body: Vec<u8> = chunks.into_iter().map(Result::unwrap).collect::<Vec<_>>().concat();
+    assert_eq!(body, b"streaming!");
+    server.join().unwrap();
+}
+
+#[test]
+fn connection_refused() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();     // closed again
+    let mut rt = Runtime::with_workers(1);
+    let result = rt.block_on(Http::get(&format!("http://127.0.0.1:{port}/")));
+    assert!(matches!(result, Err(HttpError::Connect(_))));
+}
@@ -0,0 +1,149 @@
+/*
+Offline tests for the Unix-only sources:
+    - a pipe carries bytes from its Sender to its Receiver, which reads EOF once the Sender is dropped
+    - the HTTP client talks to a server task accepting on an AsyncUnixListener in a temporary directory
+ */
+#![cfg(unix)]
+
+use c_runtime_executor::{
+    http::Http,
+    io::{AsyncRead, AsyncWrite},
+    net::{AsyncUnixListener, AsyncUnixStream},
+    pipe::{self, Receiver, Sender},
+    runtime::{spawn, JoinHandle, Runtime, Waker},
+    Future, PollState,
+};
+use std::{env, fs, io, process};
+
+// Writes `message` into the pipe, closes the Sender and reads everything back from the Receiver
+struct RoundTrip {
+    sender: Option<Sender>,
+    receiver: Receiver,
+    message: &'static [u8],
+    received: Vec<u8>,
+}
+
+impl Future for RoundTrip {
+    type Output = io::Result<Vec<u8>>;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if let Some(sender) = &mut self.sender {
+            while !self.message.is_empty() {
+                match sender.poll_write(waker, self.message) {
+                    PollState::Ready(Ok(n)) => self.message = &self.message[n..],
+                    PollState::Ready(Err(e)) => return PollState::Ready(Err(e)),
+                    PollState::NotReady => return PollState::NotReady,
+                }
+            }
+            self.sender = None;         // EOF for the Receiver
+        }
+        match self.receiver.read_to_end(&mut self.received).poll(waker) {
+            PollState::Ready(Ok(_)) => PollState::Ready(Ok(std::mem::take(&mut self.received))),
+            PollState::Ready(Err(e)) => PollState::Ready(Err(e)),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+#[test]
+fn pipe_round_trip() {
+    let mut rt = Runtime::with_workers(1);
+    let (sender, receiver) = {
+        let _context = rt.enter();
+        pipe::pipe().unwrap()
+    };
+    let round_trip = RoundTrip { sender: Some(sender), receiver, message: b"through the pipe", received: vec![] };
+    assert_eq!(rt.block_on(round_trip).unwrap(), b"through the pipe");
+    assert_eq!(rt.reactor().live_registrations(), 0);
+}
+
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+
+// Accepts one connection, reads the request head and answers it with RESPONSE; resolves to the head
+struct ServeOnce {
+    listener: AsyncUnixListener,
+    stream: Option<AsyncUnixStream>,
+    head: Vec<u8>,
+    response: &'static [u8],    // what's left to write
+}
+
+impl Future for ServeOnce {
+    type Output = String;
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => match self.listener.poll_accept(waker) {
+                PollState::Ready(result) => self.stream.insert(result.unwrap().0),
+                PollState::NotReady => return PollState::NotReady,
+            },
+        };
+        while !self.head.ends_with(b"\r\n\r\n") {
+            let mut chunk = [0u8; 1024];
+            match stream.poll_read(waker, &mut chunk) {
+                PollState::Ready(Ok(0)) => panic!("client closed the connection before sending a request"),
+                PollState::Ready(result) => self.head.extend_from_slice(&chunk[..result.unwrap()]),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        while !self.response.is_empty() {
+            match stream.poll_write(waker, self.response) {
+                PollState::Ready(result) => self.response = &self.response[result.unwrap()..],
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        PollState::Ready(String::from_utf8(std::mem::take(&mut self.head)).unwrap())
+    }
+}
+
+// Spawns the server on the first poll, then sends the request; resolves to the response and the received head
+struct Exchange<F: Future> {
+    server: Option<ServeOnce>,
+    handle: Option<JoinHandle<String>>,
+    request: F,
+    response: Option<F::Output>,
+}
+
+impl<F: Future> Future for Exchange<F> {
+    type Output = (F::Output, String);
+
+    fn poll(&mut self, waker: &Waker) -> PollState<Self::Output> {
+        if let Some(server) = self.server.take() {
+            self.handle = Some(spawn(server));
+        }
+        if self.response.is_none() {
+            match self.request.poll(waker) {
+                PollState::Ready(response) => self.response = Some(response),
+                PollState::NotReady => return PollState::NotReady,
+            }
+        }
+        match self.handle.as_mut().unwrap().poll(waker) {
+            PollState::Ready(head) => PollState::Ready((self.response.take().unwrap(), head.unwrap())),
+            PollState::NotReady => PollState::NotReady,
+        }
+    }
+}
+
+#[test]
+fn http_request_over_unix_listener() {
+    let dir = env::temp_dir().join(format!("c-runtime-executor-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("http.sock");
+    let _ = fs::remove_file(&path);
+
+    let mut rt = Runtime::with_workers(2);
+    let listener = {
+        let _context = rt.enter();
+        AsyncUnixListener::bind(&path).unwrap()
+    };
+    let server = ServeOnce { listener, stream: None, head: vec![], response: RESPONSE };
+    let request = Http::client("http://localhost").unix_socket(&path).get("/status");
+    let (response, head) = rt.block_on(Exchange { server: Some(server), handle: None, request, response: None });
+    fs::remove_dir_all(&dir).unwrap();
+
+    let response = response.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.text(), "hello");
+    assert!(head.starts_with("GET /status HTTP/1.1\r\n"), "{head}");
+    assert!(head.contains("Host: localhost\r\n"), "{head}");
+}